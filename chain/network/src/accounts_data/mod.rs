@@ -108,8 +108,12 @@ impl CacheSnapshot {
     ///   so that the node literally never signs AccountsData with colling versions.
     ///   This assumption is fragile as long as validators migrate their nodes without copying over
     ///   the whole storage.
-    fn is_new(&self, d: &SignedAccountData) -> bool {
+    ///
+    /// Data signed before `cutoff` is not new either, so that the expired data
+    /// (see `Cache::expire`) doesn't get back to the cache.
+    fn is_new(&self, d: &SignedAccountData, cutoff: Option<time::Utc>) -> bool {
         self.keys.contains(&d.account_key)
+            && cutoff.map_or(true, |cutoff| d.timestamp >= cutoff)
             && match self.data.get(&d.account_key) {
                 Some(old) if (old.version, old.timestamp) >= (d.version, d.timestamp) => false,
                 _ => true,
@@ -118,7 +122,8 @@ impl CacheSnapshot {
 
     /// Inserts d into self.data, if
     /// * `d.account_data` is in self.keys AND
-    /// * `d.version > self.data[d.account_data].version`.
+    /// * `d.version > self.data[d.account_data].version` AND
+    /// * `d.timestamp >= cutoff`.
     /// If d would override local for this node, an AccountData based on `self.local` is signed
     /// and inserted instead to rollback the overriding change (it can happen in case the node has
     /// been restarted and we observe the old value emitted by the previous run).
@@ -128,8 +133,9 @@ impl CacheSnapshot {
         &mut self,
         clock: &time::Clock,
         d: Arc<SignedAccountData>,
+        cutoff: Option<time::Utc>,
     ) -> Option<Arc<SignedAccountData>> {
-        if !self.is_new(&d) {
+        if !self.is_new(&d, cutoff) {
             return None;
         }
         let d = match &self.local {
@@ -190,16 +196,29 @@ impl CacheSnapshot {
     }
 }
 
-pub(crate) struct Cache(ArcMutex<CacheSnapshot>);
+pub(crate) struct Cache {
+    inner: ArcMutex<CacheSnapshot>,
+    /// AccountData signed longer ago than that is dropped from the cache and rejected
+    /// when received again. None means that AccountData never expires.
+    ttl: Option<time::Duration>,
+}
 
 impl Cache {
-    pub fn new() -> Self {
-        Self(ArcMutex::new(CacheSnapshot {
-            keys_by_id: Arc::new(AccountKeys::default()),
-            keys: im::HashSet::new(),
-            data: im::HashMap::new(),
-            local: None,
-        }))
+    pub fn new(ttl: Option<time::Duration>) -> Self {
+        Self {
+            inner: ArcMutex::new(CacheSnapshot {
+                keys_by_id: Arc::new(AccountKeys::default()),
+                keys: im::HashSet::new(),
+                data: im::HashMap::new(),
+                local: None,
+            }),
+            ttl,
+        }
+    }
+
+    /// AccountData signed before the returned time is expired.
+    fn cutoff(&self, now: time::Utc) -> Option<time::Utc> {
+        self.ttl.map(|ttl| now - ttl)
     }
 
     /// Updates the set of important accounts and their public keys.
@@ -210,7 +229,7 @@ impl Cache {
     ///   so a call to set_local afterwards is required to do that. For now it is fine because
     ///   the Cache owner is expected to call set_local periodically anyway.
     pub fn set_keys(&self, keys_by_id: Arc<AccountKeys>) -> bool {
-        self.0
+        self.inner
             .try_update(|mut inner| {
                 // Skip further processing if the key set didn't change.
                 // NOTE: if T implements Eq, then Arc<T> short circuits equality for x == x.
@@ -232,11 +251,12 @@ impl Cache {
     async fn verify(
         &self,
         data: Vec<Arc<SignedAccountData>>,
+        cutoff: Option<time::Utc>,
     ) -> (Vec<Arc<SignedAccountData>>, Option<Error>) {
         // Filter out non-interesting data, so that we never check signatures for valid non-interesting data.
         // Bad peers may force us to check signatures for fake data anyway, but we will ban them after first invalid signature.
         let mut new_data = HashMap::new();
-        let inner = self.0.load();
+        let inner = self.inner.load();
         for d in data {
             // There is a limit on the amount of RAM occupied by per-account datasets.
             // Broadcasting larger datasets is considered malicious behavior.
@@ -251,7 +271,7 @@ impl Cache {
             }
             // It is fine to broadcast data we already know about.
             // It is fine to broadcast account data that we don't care about.
-            if inner.is_new(&d) {
+            if inner.is_new(&d, cutoff) {
                 new_data.insert(d.account_key.clone(), d);
             }
         }
@@ -278,7 +298,7 @@ impl Cache {
         clock: &time::Clock,
         local: LocalData,
     ) -> Option<Arc<SignedAccountData>> {
-        self.0.update(|mut inner| {
+        self.inner.update(|mut inner| {
             let data = inner.set_local(clock, local);
             (data, inner)
         })
@@ -293,19 +313,41 @@ impl Cache {
         data: Vec<Arc<SignedAccountData>>,
     ) -> (Vec<Arc<SignedAccountData>>, Option<Error>) {
         let this = self.clone();
+        let cutoff = self.cutoff(clock.now_utc());
         // Execute verification on the rayon threadpool.
-        let (data, err) = this.verify(data).await;
+        let (data, err) = this.verify(data, cutoff).await;
         // Insert the successfully verified data, even if an error has been encountered.
-        let inserted = self.0.update(|mut inner| {
-            let inserted = data.into_iter().filter_map(|d| inner.try_insert(clock, d)).collect();
+        let inserted = self.inner.update(|mut inner| {
+            let inserted =
+                data.into_iter().filter_map(|d| inner.try_insert(clock, d, cutoff)).collect();
             (inserted, inner)
         });
         // Return the inserted data.
         (inserted, err)
     }
 
+    /// Drops the data signed longer than `ttl` ago, except for the data of this node, which is
+    /// refreshed by set_local(). Returns the number of dropped entries.
+    pub fn expire(&self, clock: &time::Clock) -> usize {
+        let Some(cutoff) = self.cutoff(clock.now_utc()) else { return 0 };
+        self.inner
+            .try_update(|mut inner| {
+                let local_key = inner.local.as_ref().map(|local| local.signer.public_key());
+                let before = inner.data.len();
+                inner
+                    .data
+                    .retain(|key, d| Some(key) == local_key.as_ref() || d.timestamp >= cutoff);
+                let expired = before - inner.data.len();
+                if expired == 0 {
+                    return Err(());
+                }
+                Ok((expired, inner))
+            })
+            .unwrap_or(0)
+    }
+
     /// Loads the current cache snapshot.
     pub fn load(&self) -> Arc<CacheSnapshot> {
-        self.0.load()
+        self.inner.load()
     }
 }
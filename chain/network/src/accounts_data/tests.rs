@@ -44,7 +44,7 @@ async fn happy_path() {
     let e0 = Arc::new(data::make_account_keys(&signers[0..5]));
     let e1 = Arc::new(data::make_account_keys(&signers[2..7]));
 
-    let cache = Arc::new(Cache::new());
+    let cache = Arc::new(Cache::new(None));
     assert_eq!(cache.load().data.values().count(), 0); // initially empty
     assert!(cache.set_keys(e0.clone()));
     assert_eq!(cache.load().data.values().count(), 0); // empty after initial set_keys.
@@ -108,7 +108,7 @@ async fn data_too_large() {
     let signers = make_signers(rng, 3);
     let e = Arc::new(data::make_account_keys(&signers));
 
-    let cache = Arc::new(Cache::new());
+    let cache = Arc::new(Cache::new(None));
     cache.set_keys(e);
     let a0 = Arc::new(make_account_data(rng, &clock.clock(), 1, &signers[0]));
     let a1 = Arc::new(make_account_data(rng, &clock.clock(), 1, &signers[1]));
@@ -147,7 +147,7 @@ async fn invalid_signature() {
     let signers = make_signers(rng, 3);
     let e = Arc::new(data::make_account_keys(&signers));
 
-    let cache = Arc::new(Cache::new());
+    let cache = Arc::new(Cache::new(None));
     cache.set_keys(e);
     let a0 = Arc::new(make_account_data(rng, &clock.clock(), 1, &signers[0]));
     let mut a1 = make_account_data(rng, &clock.clock(), 1, &signers[1]);
@@ -185,7 +185,7 @@ async fn single_account_multiple_data() {
     let signers = make_signers(rng, 3);
     let e = Arc::new(data::make_account_keys(&signers));
 
-    let cache = Arc::new(Cache::new());
+    let cache = Arc::new(Cache::new(None));
     cache.set_keys(e);
     let a0 = Arc::new(make_account_data(rng, &clock.clock(), 1, &signers[0]));
     let a1 = Arc::new(make_account_data(rng, &clock.clock(), 1, &signers[1]));
@@ -218,7 +218,7 @@ async fn set_local() {
     let e0 = Arc::new(data::make_account_keys(&signers[0..2]));
     let e1 = Arc::new(data::make_account_keys(&signers[1..3]));
 
-    let cache = Arc::new(Cache::new());
+    let cache = Arc::new(Cache::new(None));
     assert!(cache.set_keys(e0.clone()));
 
     // Set local while local.signer is in cache.keys.
@@ -281,3 +281,43 @@ async fn set_local() {
     assert_eq!(None, cache.set_local(&clock.clock(), local));
     assert_eq!([&a1, &got].as_set(), cache.load().data.values().collect());
 }
+
+#[tokio::test]
+async fn expire() {
+    init_test_logger();
+    let mut rng = make_rng(2947294234);
+    let rng = &mut rng;
+    let clock = time::FakeClock::default();
+
+    let signers: Vec<_> = make_signers(rng, 3);
+    let e = Arc::new(data::make_account_keys(&signers));
+    let ttl = time::Duration::minutes(5);
+    let cache = Arc::new(Cache::new(Some(ttl)));
+    assert!(cache.set_keys(e));
+
+    let local = LocalData {
+        signer: Arc::new(signers[2].clone()),
+        data: Arc::new(make_account_data(rng, &clock.clock(), 1, &signers[2]).data.clone()),
+    };
+    let got = cache.set_local(&clock.clock(), local).unwrap();
+    let a0 = Arc::new(make_account_data(rng, &clock.clock(), 1, &signers[0]));
+    clock.advance(time::Duration::minutes(3));
+    let a1 = Arc::new(make_account_data(rng, &clock.clock(), 1, &signers[1]));
+    let res = cache.clone().insert(&clock.clock(), vec![a0.clone(), a1.clone()]).await;
+    assert_eq!([&a0, &a1].as_set(), unwrap(&res).as_set());
+
+    // Only the data older than the ttl is dropped, except for the local data.
+    clock.advance(time::Duration::minutes(3));
+    assert_eq!(1, cache.expire(&clock.clock()));
+    assert_eq!([&a1, &got].as_set(), cache.load().data.values().collect());
+    assert_eq!(0, cache.expire(&clock.clock()));
+
+    // The expired data is rejected, when it comes back, e.g. with a full sync.
+    let res = cache.clone().insert(&clock.clock(), vec![a0.clone()]).await;
+    assert_eq!(0, unwrap(&res).len());
+    assert_eq!([&a1, &got].as_set(), cache.load().data.values().collect());
+
+    clock.advance(time::Duration::minutes(3));
+    assert_eq!(1, cache.expire(&clock.clock()));
+    assert_eq!([&got].as_set(), cache.load().data.values().collect());
+}
@@ -1,3 +1,5 @@
+use near_async::time;
+
 /// Config of a rate limiter algorithm, which behaves like a semaphore
 /// - with maximal capacity `burst`
 /// - with a new ticket added automatically every 1/qps seconds (qps stands for "queries per
@@ -25,3 +27,35 @@ impl Limit {
         Ok(())
     }
 }
+
+/// Rate limiter implementing the `Limit` algorithm for synchronous callers.
+/// Instead of waiting for a ticket, the caller is just informed whether
+/// a ticket was available.
+pub struct Limiter {
+    limit: Limit,
+    /// Number of available tickets. Fractional, so that the refill is exact.
+    tickets: f64,
+    /// Last time `tickets` have been refilled.
+    refilled_at: time::Instant,
+}
+
+impl Limiter {
+    /// Constructs a limiter with `limit.burst` tickets available.
+    pub fn new(clock: &time::Clock, limit: Limit) -> Self {
+        Self { limit, tickets: limit.burst as f64, refilled_at: clock.now() }
+    }
+
+    /// Acquires a ticket, if available.
+    /// Returns false iff the rate limit has been exceeded.
+    pub fn allow(&mut self, clock: &time::Clock) -> bool {
+        let now = clock.now();
+        let elapsed = (now - self.refilled_at).as_seconds_f64().max(0.);
+        self.tickets = (self.tickets + elapsed * self.limit.qps).min(self.limit.burst as f64);
+        self.refilled_at = now;
+        if self.tickets < 1. {
+            return false;
+        }
+        self.tickets -= 1.;
+        true
+    }
+}
@@ -1,6 +1,7 @@
 use crate::concurrency::arc_mutex::ArcMutex;
 use crate::concurrency::demux;
 use crate::concurrency::rate;
+use near_async::time;
 
 #[tokio::test]
 async fn test_demux() {
@@ -97,3 +98,23 @@ fn arc_mutex_update() {
     );
     assert_eq!(v3, *m.load());
 }
+
+#[test]
+fn rate_limiter() {
+    let clock = time::FakeClock::default();
+    let mut limiter = rate::Limiter::new(&clock.clock(), rate::Limit { qps: 2., burst: 3 });
+    for _ in 0..3 {
+        assert!(limiter.allow(&clock.clock()));
+    }
+    assert!(!limiter.allow(&clock.clock()));
+    // A new ticket is added every 1/qps seconds.
+    clock.advance(time::Duration::milliseconds(500));
+    assert!(limiter.allow(&clock.clock()));
+    assert!(!limiter.allow(&clock.clock()));
+    // Tickets never exceed burst.
+    clock.advance(time::Duration::seconds(100));
+    for _ in 0..3 {
+        assert!(limiter.allow(&clock.clock()));
+    }
+    assert!(!limiter.allow(&clock.clock()));
+}
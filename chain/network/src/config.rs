@@ -2,6 +2,7 @@ use crate::blacklist;
 use crate::concurrency::rate;
 use crate::network_protocol::PeerAddr;
 use crate::network_protocol::PeerInfo;
+use crate::network_protocol::RoutedMessageBody;
 use crate::peer_manager::peer_manager_actor::Event;
 use crate::peer_manager::peer_store;
 use crate::sink::Sink;
@@ -15,8 +16,9 @@ use near_primitives::network::PeerId;
 use near_primitives::test_utils::create_test_signer;
 use near_primitives::types::AccountId;
 use near_primitives::validator_signer::ValidatorSigner;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use strum::VariantNames as _;
 
 /// How much height horizon to give to consider peer up to date.
 pub const HIGHEST_PEER_HORIZON: u64 = 5;
@@ -78,6 +80,29 @@ pub struct Tier1 {
     /// Interval between broacasts of the list of validator's proxies.
     /// Before the broadcast, validator tries to establish all the missing connections to proxies.
     pub advertise_proxies_interval: time::Duration,
+    /// Unchanged list of proxies is broadcasted again only once that much time has passed
+    /// since it was signed. Until then the broadcast is skipped to save bandwidth.
+    pub advertise_proxies_refresh_interval: time::Duration,
+    /// Interval between STUN queries resolving the public address of this node.
+    /// Used only if the validator proxies are configured as ValidatorProxies::Dynamic.
+    pub stun_resolve_interval: time::Duration,
+    /// Delay before retrying to connect to a proxy of another TIER1 node, after a failed attempt.
+    /// The delay doubles with every consecutive failure, up to `connect_backoff_max`.
+    pub connect_backoff_min: time::Duration,
+    pub connect_backoff_max: time::Duration,
+    /// Maximal number of entries in the TIER1 route back cache.
+    pub route_back_capacity: usize,
+    /// Time after which an entry of the TIER1 route back cache expires.
+    pub route_back_ttl: time::Duration,
+    /// Interval between liveness Pings sent to every TIER1 connection.
+    pub liveness_ping_interval: time::Duration,
+    /// TIER1 connection is closed once it misses that many consecutive liveness Pongs.
+    pub liveness_max_missed_pongs: u32,
+    /// AccountData signed longer ago than that is dropped from the cache and rejected when
+    /// received again, so that stale proxies are not connected to. Has to be larger than
+    /// advertise_proxies_refresh_interval, otherwise the data of live TIER1 nodes would
+    /// expire as well.
+    pub accounts_data_ttl: time::Duration,
     /// Support for gradual TIER1 feature rollout:
     /// - establishing connection to node's own proxies is always enabled (it is a part of peer
     ///   discovery mechanism). Note that unless the proxy has enable_inbound set, establishing
@@ -130,6 +155,22 @@ pub struct NetworkConfig {
     /// This is used to avoid infinite loop because of inconsistent view of the network
     /// by different nodes.
     pub routed_message_ttl: u8,
+    /// Overrides of routed_message_ttl for specific message types,
+    /// keyed by the name of the RoutedMessageBody variant (for example "StateRequestPart").
+    pub routed_message_ttl_overrides: HashMap<String, u8>,
+    /// Number of times an important message is sent over TIER2.
+    /// Messages are sent multiple times to reduce the chance that they are lost.
+    /// Receivers deduplicate the copies, so that they are handled just once.
+    pub important_message_resent_count: usize,
+    /// How long an important message to an unknown/unreachable account is kept for
+    /// re-attempting the routing, before being dropped.
+    pub unroutable_message_retry_window: time::Duration,
+    /// Maximum number of ForwardTx messages accepted between block messages.
+    /// Further ForwardTx messages are dropped, until the next block arrives.
+    /// The purpose of this limit is to ensure we do not spend too much time deserializing and
+    /// dispatching transactions when we should be focusing on consensus-related messages.
+    /// None means no limit.
+    pub max_forward_txs_per_block: Option<usize>,
     /// Maximum number of routes that we should keep track for each Account id in the Routing Table.
     pub max_routes_to_store: usize,
     /// Height horizon for highest height peers
@@ -151,6 +192,9 @@ pub struct NetworkConfig {
     pub accounts_data_broadcast_rate_limit: rate::Limit,
     /// Maximal rate at which RoutingTable can be recomputed.
     pub routing_table_update_rate_limit: rate::Limit,
+    /// Maximal rate at which routed messages are accepted from a single peer.
+    /// Messages above the limit are dropped.
+    pub peer_routed_message_rate_limit: rate::Limit,
     /// Config of the TIER1 network.
     pub tier1: Option<Tier1>,
 
@@ -183,6 +227,15 @@ impl NetworkConfig {
         if let Some(routed_message_ttl) = overrides.routed_message_ttl {
             self.routed_message_ttl = routed_message_ttl
         }
+        if let Some(routed_message_ttl_overrides) = overrides.routed_message_ttl_overrides {
+            self.routed_message_ttl_overrides = routed_message_ttl_overrides
+        }
+        if let Some(important_message_resent_count) = overrides.important_message_resent_count {
+            self.important_message_resent_count = important_message_resent_count
+        }
+        if let Some(max_forward_txs_per_block) = overrides.max_forward_txs_per_block {
+            self.max_forward_txs_per_block = Some(max_forward_txs_per_block)
+        }
         if let Some(max_routes_to_store) = overrides.max_routes_to_store {
             self.max_routes_to_store = max_routes_to_store
         }
@@ -207,6 +260,12 @@ impl NetworkConfig {
         ) {
             self.routing_table_update_rate_limit = rate::Limit { qps, burst }
         }
+        if let (Some(qps), Some(burst)) = (
+            overrides.peer_routed_message_rate_limit_qps,
+            overrides.peer_routed_message_rate_limit_burst,
+        ) {
+            self.peer_routed_message_rate_limit = rate::Limit { qps, burst }
+        }
     }
 
     pub fn new(
@@ -312,6 +371,10 @@ impl NetworkConfig {
             peer_stats_period: cfg.peer_stats_period.try_into()?,
             ttl_account_id_router: cfg.ttl_account_id_router.try_into()?,
             routed_message_ttl: ROUTED_MESSAGE_TTL,
+            routed_message_ttl_overrides: HashMap::new(),
+            important_message_resent_count: 3,
+            unroutable_message_retry_window: time::Duration::seconds(5),
+            max_forward_txs_per_block: Some(1000),
             max_routes_to_store: MAX_ROUTES_TO_STORE,
             highest_peer_horizon: HIGHEST_PEER_HORIZON,
             push_info_period: time::Duration::milliseconds(100),
@@ -319,10 +382,20 @@ impl NetworkConfig {
             archive,
             accounts_data_broadcast_rate_limit: rate::Limit { qps: 0.1, burst: 1 },
             routing_table_update_rate_limit: rate::Limit { qps: 1., burst: 1 },
+            peer_routed_message_rate_limit: rate::Limit { qps: 1000., burst: 10000 },
             tier1: Some(Tier1 {
                 connect_interval: cfg.experimental.tier1_connect_interval.try_into()?,
                 new_connections_per_attempt: cfg.experimental.tier1_new_connections_per_attempt,
                 advertise_proxies_interval: time::Duration::minutes(15),
+                advertise_proxies_refresh_interval: time::Duration::hours(1),
+                stun_resolve_interval: time::Duration::minutes(5),
+                connect_backoff_min: time::Duration::seconds(30),
+                connect_backoff_max: time::Duration::minutes(30),
+                route_back_capacity: 100_000,
+                route_back_ttl: time::Duration::minutes(2),
+                liveness_ping_interval: time::Duration::seconds(30),
+                liveness_max_missed_pongs: 3,
+                accounts_data_ttl: time::Duration::hours(2),
                 enable_inbound: cfg.experimental.tier1_enable_inbound,
                 enable_outbound: cfg.experimental.tier1_enable_outbound,
             }),
@@ -338,6 +411,13 @@ impl NetworkConfig {
         Ok(this)
     }
 
+    /// TTL of a routed message with the given body: the override for the message type,
+    /// if configured, or routed_message_ttl otherwise.
+    pub fn routed_message_ttl(&self, body: &RoutedMessageBody) -> u8 {
+        let variant: &'static str = body.into();
+        self.routed_message_ttl_overrides.get(variant).copied().unwrap_or(self.routed_message_ttl)
+    }
+
     pub fn node_id(&self) -> PeerId {
         PeerId::new(self.node_key.public_key())
     }
@@ -379,6 +459,10 @@ impl NetworkConfig {
             peer_stats_period: time::Duration::seconds(5),
             ttl_account_id_router: time::Duration::seconds(60 * 60),
             routed_message_ttl: ROUTED_MESSAGE_TTL,
+            routed_message_ttl_overrides: HashMap::new(),
+            important_message_resent_count: 3,
+            unroutable_message_retry_window: time::Duration::seconds(5),
+            max_forward_txs_per_block: Some(1000),
             max_routes_to_store: 1,
             highest_peer_horizon: 5,
             push_info_period: time::Duration::milliseconds(100),
@@ -387,12 +471,25 @@ impl NetworkConfig {
             archive: false,
             accounts_data_broadcast_rate_limit: rate::Limit { qps: 100., burst: 1000000 },
             routing_table_update_rate_limit: rate::Limit { qps: 10., burst: 1 },
+            peer_routed_message_rate_limit: rate::Limit { qps: 100000., burst: 1000000 },
             tier1: Some(Tier1 {
                 // Interval is very large, so that it doesn't happen spontaneously in tests.
                 // It should rather be triggered manually in tests.
                 connect_interval: time::Duration::hours(1000),
                 new_connections_per_attempt: 10000,
                 advertise_proxies_interval: time::Duration::hours(1000),
+                // Every call to tier1_advertise_proxies() broadcasts a new version of AccountData,
+                // unless a test overrides it.
+                advertise_proxies_refresh_interval: time::Duration::ZERO,
+                stun_resolve_interval: time::Duration::hours(1000),
+                // Backoff is disabled, so that tests can retry connecting at will.
+                connect_backoff_min: time::Duration::ZERO,
+                connect_backoff_max: time::Duration::ZERO,
+                route_back_capacity: 100_000,
+                route_back_ttl: time::Duration::minutes(2),
+                liveness_ping_interval: time::Duration::hours(1000),
+                liveness_max_missed_pongs: 3,
+                accounts_data_ttl: time::Duration::hours(1000),
                 enable_inbound: true,
                 enable_outbound: true,
             }),
@@ -440,12 +537,36 @@ impl NetworkConfig {
             );
         }
 
+        for variant in self.routed_message_ttl_overrides.keys() {
+            if !RoutedMessageBody::VARIANTS.contains(&variant.as_str()) {
+                anyhow::bail!(
+                    "routed_message_ttl_overrides: {variant} is not a routed message type"
+                );
+            }
+        }
+
+        if self.important_message_resent_count == 0 {
+            anyhow::bail!("important_message_resent_count has to be positive");
+        }
+
+        if let Some(tier1) = &self.tier1 {
+            if tier1.route_back_capacity == 0 {
+                anyhow::bail!("tier1.route_back_capacity has to be positive");
+            }
+            if tier1.accounts_data_ttl <= tier1.advertise_proxies_refresh_interval {
+                anyhow::bail!(
+                    "tier1.accounts_data_ttl has to be larger than tier1.advertise_proxies_refresh_interval"
+                );
+            }
+        }
+
         self.accounts_data_broadcast_rate_limit
             .validate()
             .context("accounts_Data_broadcast_rate_limit")?;
         self.routing_table_update_rate_limit
             .validate()
             .context("routing_table_update_rate_limit")?;
+        self.peer_routed_message_rate_limit.validate().context("peer_routed_message_rate_limit")?;
         Ok(VerifiedConfig { node_id: self.node_id(), inner: self })
     }
 }
@@ -508,6 +629,19 @@ mod test {
         let mut nc = config::NetworkConfig::from_seed("123", tcp::ListenerAddr::reserve_for_test());
         nc.peer_recent_time_window = UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE;
         assert!(nc.verify().is_err());
+
+        let mut nc = config::NetworkConfig::from_seed("123", tcp::ListenerAddr::reserve_for_test());
+        nc.routed_message_ttl_overrides.insert("NotAMessage".to_string(), 1);
+        assert!(nc.verify().is_err());
+
+        let mut nc = config::NetworkConfig::from_seed("123", tcp::ListenerAddr::reserve_for_test());
+        nc.tier1.as_mut().unwrap().route_back_capacity = 0;
+        assert!(nc.verify().is_err());
+
+        let mut nc = config::NetworkConfig::from_seed("123", tcp::ListenerAddr::reserve_for_test());
+        let tier1 = nc.tier1.as_mut().unwrap();
+        tier1.accounts_data_ttl = tier1.advertise_proxies_refresh_interval;
+        assert!(nc.verify().is_err());
     }
 
     #[test]
@@ -541,6 +675,21 @@ mod test {
                 &after.routed_message_ttl,
                 &overrides.routed_message_ttl
             ));
+            assert!(check_override_field(
+                &before.routed_message_ttl_overrides,
+                &after.routed_message_ttl_overrides,
+                &overrides.routed_message_ttl_overrides
+            ));
+            assert!(check_override_field(
+                &before.important_message_resent_count,
+                &after.important_message_resent_count,
+                &overrides.important_message_resent_count
+            ));
+            assert!(check_override_field(
+                &before.max_forward_txs_per_block,
+                &after.max_forward_txs_per_block,
+                &overrides.max_forward_txs_per_block.map(Some)
+            ));
             assert!(check_override_field(
                 &before.max_routes_to_store,
                 &after.max_routes_to_store,
@@ -579,6 +728,8 @@ mod test {
         overrides.connect_to_reliable_peers_on_startup = Some(false);
         overrides.max_send_peers = Some(42);
         overrides.routed_message_ttl = Some(43);
+        overrides.important_message_resent_count = Some(2);
+        overrides.max_forward_txs_per_block = Some(46);
         overrides.accounts_data_broadcast_rate_limit_burst = Some(44);
         overrides.accounts_data_broadcast_rate_limit_qps = Some(45.0);
 
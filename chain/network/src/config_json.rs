@@ -1,5 +1,6 @@
 use crate::network_protocol::PeerAddr;
 use crate::stun;
+use std::collections::HashMap;
 use std::time::Duration;
 
 /// Time to persist Accounts Id in the router without removing them in seconds.
@@ -257,6 +258,9 @@ pub struct NetworkConfigOverrides {
     pub connect_to_reliable_peers_on_startup: Option<bool>,
     pub max_send_peers: Option<u32>,
     pub routed_message_ttl: Option<u8>,
+    pub routed_message_ttl_overrides: Option<HashMap<String, u8>>,
+    pub important_message_resent_count: Option<usize>,
+    pub max_forward_txs_per_block: Option<usize>,
     pub max_routes_to_store: Option<usize>,
     pub highest_peer_horizon: Option<u64>,
     pub push_info_period_millis: Option<i64>,
@@ -265,6 +269,8 @@ pub struct NetworkConfigOverrides {
     pub accounts_data_broadcast_rate_limit_qps: Option<f64>,
     pub routing_table_update_rate_limit_burst: Option<u64>,
     pub routing_table_update_rate_limit_qps: Option<f64>,
+    pub peer_routed_message_rate_limit_burst: Option<u64>,
+    pub peer_routed_message_rate_limit_qps: Option<f64>,
 }
 
 impl Default for ExperimentalConfig {
@@ -452,7 +452,13 @@ impl PeerMessage {
 
 // TODO(#1313): Use Box
 #[derive(
-    borsh::BorshSerialize, borsh::BorshDeserialize, PartialEq, Eq, Clone, strum::IntoStaticStr,
+    borsh::BorshSerialize,
+    borsh::BorshDeserialize,
+    PartialEq,
+    Eq,
+    Clone,
+    strum::IntoStaticStr,
+    strum::EnumVariantNames,
 )]
 pub enum RoutedMessageBody {
     BlockApproval(Approval),
@@ -509,6 +515,22 @@ impl RoutedMessageBody {
             _ => false,
         }
     }
+
+    /// Returns the message to be relayed via a TIER1 proxy.
+    /// To bound the size of the relayed message, a PartialEncodedChunk is stripped
+    /// of everything but the header. The full chunk is still delivered over TIER2.
+    pub fn for_tier1_proxy(&self) -> Self {
+        match self {
+            RoutedMessageBody::VersionedPartialEncodedChunk(chunk) => {
+                RoutedMessageBody::VersionedPartialEncodedChunk(PartialEncodedChunk::new(
+                    chunk.cloned_header(),
+                    vec![],
+                    vec![],
+                ))
+            }
+            msg => msg.clone(),
+        }
+    }
 }
 
 impl fmt::Debug for RoutedMessageBody {
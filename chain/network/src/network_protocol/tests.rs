@@ -170,3 +170,31 @@ fn serialize_deserialize() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn partial_encoded_chunk_for_tier1_proxy() {
+    use borsh::BorshSerialize as _;
+    let mut rng = make_rng(19385389);
+    let mut clock = time::FakeClock::default();
+    let chain = data::Chain::make(&mut clock, &mut rng, 12);
+    let chunk_hash = chain.blocks[3].chunks()[0].chunk_hash();
+    let chunk = chain.chunks[&chunk_hash].clone();
+    let header = chunk.cloned_header();
+    let direct = RoutedMessageBody::VersionedPartialEncodedChunk(PartialEncodedChunk::new(
+        header.clone(),
+        data::make_chunk_parts(chunk),
+        vec![],
+    ));
+    let proxied = direct.for_tier1_proxy();
+    let want = RoutedMessageBody::VersionedPartialEncodedChunk(PartialEncodedChunk::new(
+        header,
+        vec![],
+        vec![],
+    ));
+    assert_eq!(want, proxied);
+    assert!(proxied.try_to_vec().unwrap().len() < direct.try_to_vec().unwrap().len());
+
+    // Other messages are relayed as is.
+    let ping = RoutedMessageBody::Ping(Ping { nonce: 7, source: data::make_peer_id(&mut rng) });
+    assert_eq!(ping, ping.for_tier1_proxy());
+}
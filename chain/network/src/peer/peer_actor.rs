@@ -52,10 +52,6 @@ const REQUEST_PEERS_INTERVAL: time::Duration = time::Duration::seconds(60);
 /// Maximal allowed UTC clock skew between this node and the peer.
 const MAX_CLOCK_SKEW: time::Duration = time::Duration::minutes(30);
 
-/// Maximum number of transaction messages we will accept between block messages.
-/// The purpose of this constant is to ensure we do not spend too much time deserializing and
-/// dispatching transactions when we should be focusing on consensus-related messages.
-const MAX_TRANSACTIONS_PER_BLOCK_MESSAGE: usize = 1000;
 /// Limit cache size of 1000 messages
 const ROUTED_MESSAGE_CACHE_SIZE: usize = 1000;
 /// Duplicated messages will be dropped if routed through the same peer multiple times.
@@ -241,17 +237,13 @@ impl PeerActor {
     ) -> Result<(actix::Addr<Self>, HandshakeSignal), ClosingReason> {
         let connecting_status = match &stream.type_ {
             tcp::StreamType::Inbound => ConnectingStatus::Inbound(
-                network_state
-                    .inbound_handshake_permits
-                    .clone()
-                    .try_acquire_owned()
+                InboundHandshakePermit::acquire(&network_state.inbound_handshake_permits)
                     .map_err(|_| ClosingReason::TooManyInbound)?,
             ),
             tcp::StreamType::Outbound { tier, peer_id } => ConnectingStatus::Outbound {
                 _permit: match tier {
                     tcp::Tier::T1 => network_state
-                        .tier1
-                        .start_outbound(peer_id.clone())
+                        .tier1_start_outbound(peer_id.clone())
                         .map_err(ClosingReason::OutboundNotAllowed)?,
                     tcp::Tier::T2 => {
                         // A loop connection is not allowed on TIER2
@@ -1002,7 +994,7 @@ impl PeerActor {
             .delayed_push(|| Event::MessageProcessed(conn.tier, msg.clone()));
         let was_requested = match &msg {
             PeerMessage::Block(block) => {
-                self.network_state.txns_since_last_block.store(0, Ordering::Release);
+                self.network_state.reset_txns_since_last_block();
                 let hash = *block.hash();
                 let height = block.header().height();
                 conn.last_block.rcu(|last_block| {
@@ -1282,6 +1274,11 @@ impl PeerActor {
                     "Received routed message from {} to {:?}.",
                     self.peer_info,
                     msg.target);
+                if !self.network_state.allow_routed_message(&self.clock, &conn.peer_info.id) {
+                    self.network_state.config.event_sink.push(Event::RoutedMessageDropped);
+                    tracing::debug!(target: "network", from = ?conn.peer_info.id, msg_type = msg.body_variant(), "Dropping routed message: peer rate limit exceeded");
+                    return;
+                }
                 let for_me = self.network_state.message_for_me(&msg.target);
                 if for_me {
                     // Check if we have already received this message.
@@ -1309,15 +1306,14 @@ impl PeerActor {
                 if let RoutedMessageBody::ForwardTx(_) = &msg.body {
                     // Check whenever we exceeded number of transactions we got since last block.
                     // If so, drop the transaction.
-                    let r = self.network_state.txns_since_last_block.load(Ordering::Acquire);
                     // TODO(gprusak): this constraint doesn't take into consideration such
                     // parameters as number of nodes or number of shards. Reconsider why do we need
                     // this and whether this is really the right way of handling it.
-                    if r > MAX_TRANSACTIONS_PER_BLOCK_MESSAGE {
+                    if !self.network_state.register_forward_tx() {
                         metrics::MessageDropped::TransactionsPerBlockExceeded.inc(&msg.body);
+                        self.network_state.config.event_sink.push(Event::RoutedMessageDropped);
                         return;
                     }
-                    self.network_state.txns_since_last_block.fetch_add(1, Ordering::AcqRel);
                 }
                 self.routed_message_cache.put(key, now);
 
@@ -1329,6 +1325,23 @@ impl PeerActor {
 
                 self.add_route_back(&conn, msg.as_ref());
                 if for_me {
+                    // Important messages are sent multiple times over TIER2, handle only the
+                    // first copy. The hash covers the author, target and body of the message,
+                    // so distinct messages are not affected.
+                    if conn.tier == tcp::Tier::T2
+                        && msg.body.is_important()
+                        && self
+                            .network_state
+                            .recent_important_messages
+                            .lock()
+                            .put(msg.hash(), ())
+                            .is_some()
+                    {
+                        metrics::MessageDropped::Duplicate.inc(&msg.body);
+                        self.network_state.config.event_sink.push(Event::RoutedMessageDropped);
+                        tracing::debug!(target: "network", author = ?msg.author, "Dropping duplicated important message");
+                        return;
+                    }
                     // Handle Ping and Pong message if they are for us without sending to client.
                     // i.e. Return false in case of Ping and Pong
                     match &msg.body {
@@ -1348,6 +1361,11 @@ impl PeerActor {
                                 .push(Event::MessageProcessed(conn.tier, PeerMessage::Routed(msg)));
                         }
                         RoutedMessageBody::Pong(pong) => {
+                            self.network_state.rtt.lock().pong_received(
+                                self.clock.now(),
+                                pong.nonce,
+                                &pong.source,
+                            );
                             self.network_state.config.event_sink.push(Event::Pong(pong.clone()));
                             self.network_state
                                 .config
@@ -1389,7 +1407,7 @@ impl PeerActor {
         let old = network_state
             .graph
             .routing_table
-            .get_broadcasted_announces(rtu.accounts.iter().map(|a| &a.account_id));
+            .get_broadcasted_announces(clock, rtu.accounts.iter().map(|a| &a.account_id));
         let accounts: Vec<(AnnounceAccount, Option<EpochId>)> = rtu
             .accounts
             .into_iter()
@@ -1400,7 +1418,7 @@ impl PeerActor {
             .collect();
         match network_state.client.announce_account(accounts).await {
             Err(ban_reason) => conn.stop(Some(ban_reason)),
-            Ok(accounts) => network_state.add_accounts(accounts).await,
+            Ok(accounts) => network_state.add_accounts(clock, accounts).await,
         }
     }
 }
@@ -1646,7 +1664,30 @@ impl actix::Handler<WithSpanContext<Stop>> for PeerActor {
     }
 }
 
-type InboundHandshakePermit = tokio::sync::OwnedSemaphorePermit;
+/// Permit for an inbound handshake.
+/// Keeps the INBOUND_HANDSHAKE_PERMITS_AVAILABLE metric up to date.
+#[derive(Debug)]
+struct InboundHandshakePermit {
+    semaphore: Arc<tokio::sync::Semaphore>,
+    permit: Option<tokio::sync::OwnedSemaphorePermit>,
+}
+
+impl InboundHandshakePermit {
+    fn acquire(
+        semaphore: &Arc<tokio::sync::Semaphore>,
+    ) -> Result<Self, tokio::sync::TryAcquireError> {
+        let permit = semaphore.clone().try_acquire_owned()?;
+        metrics::INBOUND_HANDSHAKE_PERMITS_AVAILABLE.set(semaphore.available_permits() as i64);
+        Ok(Self { semaphore: semaphore.clone(), permit: Some(permit) })
+    }
+}
+
+impl Drop for InboundHandshakePermit {
+    fn drop(&mut self) {
+        drop(self.permit.take());
+        metrics::INBOUND_HANDSHAKE_PERMITS_AVAILABLE.set(self.semaphore.available_permits() as i64);
+    }
+}
 
 #[derive(Debug)]
 enum ConnectingStatus {
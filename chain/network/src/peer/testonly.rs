@@ -100,16 +100,19 @@ impl PeerHandle {
         let store = store::Store::from(near_store::db::TestDB::new());
         let mut network_cfg = cfg.network.clone();
         network_cfg.event_sink = send.sink().compose(Event::Network);
-        let network_state = Arc::new(NetworkState::new(
-            &clock,
-            store.clone(),
-            peer_store::PeerStore::new(&clock, network_cfg.peer_store.clone()).unwrap(),
-            network_cfg.verify().unwrap(),
-            cfg.chain.genesis_id.clone(),
-            fc.clone(),
-            fc.as_sender(),
-            vec![],
-        ));
+        let network_state = Arc::new(
+            NetworkState::new(
+                &clock,
+                store.clone(),
+                peer_store::PeerStore::new(&clock, network_cfg.peer_store.clone()).unwrap(),
+                network_cfg.verify().unwrap(),
+                cfg.chain.genesis_id.clone(),
+                fc.clone(),
+                fc.as_sender(),
+                vec![],
+            )
+            .unwrap(),
+        );
         let actix = ActixSystem::spawn({
             let clock = clock.clone();
             let cfg = cfg.clone();
@@ -14,6 +14,7 @@ use crate::types::{BlockInfo, FullPeerInfo, PeerChainInfo, PeerType, ReasonForBa
 use arc_swap::ArcSwap;
 use near_async::time;
 use near_crypto::PublicKey;
+use near_o11y::metrics::IntGauge;
 use near_o11y::WithSpanContextExt;
 use near_primitives::block::GenesisId;
 use near_primitives::network::PeerId;
@@ -227,6 +228,22 @@ pub(crate) struct PoolSnapshot {
     pub outbound_handshakes: im::HashSet<PeerId>,
     /// Inbound end of the loop connection. The outbound end is added to the `ready` set.
     pub loop_inbound: Option<Arc<Connection>>,
+    /// Gauges reporting the size of the pool.
+    metrics: PoolMetrics,
+}
+
+#[derive(Clone)]
+struct PoolMetrics {
+    ready: IntGauge,
+    outbound_handshakes: IntGauge,
+}
+
+impl PoolSnapshot {
+    /// Updates the gauges. Should be called whenever the pool changes.
+    fn report_metrics(&self) {
+        self.metrics.ready.set(self.ready.len() as i64);
+        self.metrics.outbound_handshakes.set(self.outbound_handshakes.len() as i64);
+    }
 }
 
 pub(crate) struct OutboundHandshakePermit(PeerId, Weak<ArcMutex<PoolSnapshot>>);
@@ -248,6 +265,7 @@ impl Drop for OutboundHandshakePermit {
         if let Some(pool) = self.1.upgrade() {
             pool.update(|mut pool| {
                 pool.outbound_handshakes.remove(&self.0);
+                pool.report_metrics();
                 ((), pool)
             });
         }
@@ -272,14 +290,22 @@ pub(crate) enum PoolError {
 }
 
 impl Pool {
-    pub fn new(me: PeerId) -> Pool {
-        Self(Arc::new(ArcMutex::new(PoolSnapshot {
+    /// `name` identifies the pool in the metrics.
+    pub fn new(me: PeerId, name: &str) -> Pool {
+        let pool = PoolSnapshot {
             loop_inbound: None,
             me,
             ready: im::HashMap::new(),
             ready_by_account_key: im::HashMap::new(),
             outbound_handshakes: im::HashSet::new(),
-        })))
+            metrics: PoolMetrics {
+                ready: metrics::CONNECTION_POOL_READY.with_label_values(&[name]),
+                outbound_handshakes: metrics::CONNECTION_POOL_OUTBOUND_HANDSHAKES
+                    .with_label_values(&[name]),
+            },
+        };
+        pool.report_metrics();
+        Self(Arc::new(ArcMutex::new(pool)))
     }
 
     pub fn load(&self) -> Arc<PoolSnapshot> {
@@ -305,6 +331,7 @@ impl Pool {
                     return Err(PoolError::UnexpectedLoopConnection);
                 }
                 pool.loop_inbound = Some(peer);
+                pool.report_metrics();
                 return Ok(((), pool));
             }
             match peer.peer_type {
@@ -369,6 +396,7 @@ impl Pool {
                     return Err(err);
                 }
             }
+            pool.report_metrics();
             Ok(((), pool))
         })
     }
@@ -393,6 +421,7 @@ impl Pool {
                 return Err(PoolError::AlreadyStartedConnecting);
             }
             pool.outbound_handshakes.insert(peer_id.clone());
+            pool.report_metrics();
             Ok((OutboundHandshakePermit(peer_id, Arc::downgrade(&self.0)), pool))
         })
     }
@@ -413,6 +442,7 @@ impl Pool {
                     _ => {}
                 }
             }
+            pool.report_metrics();
             ((), pool)
         });
     }
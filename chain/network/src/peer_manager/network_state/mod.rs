@@ -1,11 +1,12 @@
 use crate::accounts_data;
 use crate::client;
 use crate::concurrency::demux;
+use crate::concurrency::rate;
 use crate::concurrency::runtime::Runtime;
 use crate::config;
 use crate::network_protocol::{
-    Edge, EdgeState, PartialEdgeInfo, PeerIdOrHash, PeerInfo, PeerMessage, RawRoutedMessage,
-    RoutedMessageBody, RoutedMessageV2, SignedAccountData,
+    Edge, EdgeState, PartialEdgeInfo, PeerAddr, PeerIdOrHash, PeerInfo, PeerMessage,
+    RawRoutedMessage, RoutedMessageBody, RoutedMessageV2, SignedAccountData,
 };
 use crate::peer::peer_actor::PeerActor;
 use crate::peer::peer_actor::{ClosingReason, ConnectionClosedEvent};
@@ -14,7 +15,9 @@ use crate::peer_manager::connection_store;
 use crate::peer_manager::peer_manager_actor::Event;
 use crate::peer_manager::peer_store;
 use crate::private_actix::RegisterPeerError;
-use crate::routing::route_back_cache::RouteBackCache;
+use crate::routing::retry_buffer::{PendingMessage, RetryBuffer};
+use crate::routing::route_back_cache::{self, RouteBackCache};
+use crate::routing::rtt::RttTracker;
 use crate::shards_manager::ShardsManagerRequestFromNetwork;
 use crate::stats::metrics;
 use crate::store;
@@ -29,32 +32,49 @@ use near_primitives::hash::CryptoHash;
 use near_primitives::network::PeerId;
 use near_primitives::types::AccountId;
 use parking_lot::Mutex;
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tracing::Instrument as _;
 
-mod routing;
+pub(crate) mod routing;
 mod tier1;
 
 /// Limit number of pending Peer actors to avoid OOM.
 pub(crate) const LIMIT_PENDING_PEERS: usize = 60;
 
-/// Send important messages three times.
-/// We send these messages multiple times to reduce the chance that they are lost
-const IMPORTANT_MESSAGE_RESENT_COUNT: usize = 3;
-
 /// Size of LRU cache size of recent routed messages.
 /// It should be large enough to detect duplicates (i.e. all messages received during
 /// production of 1 block should fit).
 const RECENT_ROUTED_MESSAGES_CACHE_SIZE: usize = 10000;
 
+/// Size of LRU cache of recently received important messages.
+/// Important messages are sent multiple times, and the copies are dropped on receipt.
+const RECENT_IMPORTANT_MESSAGES_CACHE_SIZE: usize = 1000;
+
+/// Maximal number of important messages buffered while waiting for a route to their target.
+const UNROUTABLE_MESSAGES_CAPACITY: usize = 1000;
+
+/// How long to wait for a Pong, before considering the Ping lost.
+const PING_TIMEOUT: time::Duration = time::Duration::seconds(10);
+
 /// How long a peer has to be unreachable, until we prune it from the in-memory graph.
 const PRUNE_UNREACHABLE_PEERS_AFTER: time::Duration = time::Duration::hours(1);
 
 /// Remove the edges that were created more that this duration ago.
 pub const PRUNE_EDGES_AFTER: time::Duration = time::Duration::minutes(30);
 
+/// Maximal number of edges kept in the in-memory graph.
+/// It is way above the size of the current network and protects against edge flooding.
+const MAX_GRAPH_EDGES: usize = 1_000_000;
+
+/// Maximal number of edges verified in a single rayon task.
+const EDGE_VERIFICATION_BATCH_SIZE: usize = 1000;
+
+/// Number of the most recent transitions recorded for each edge adjacent to this node.
+const EDGE_HISTORY_LEN: usize = 20;
+
 /// How long to wait between reconnection attempts to the same peer
 pub(crate) const RECONNECT_ATTEMPT_INTERVAL: time::Duration = time::Duration::seconds(10);
 
@@ -79,6 +99,14 @@ pub(crate) struct WhitelistNode {
     account_id: Option<AccountId>,
 }
 
+/// Rate limiter of the routed messages received from a single peer.
+struct RoutedMessageLimiter {
+    limiter: rate::Limiter,
+    /// PEER_RATE_LIMITED counter of the peer. It is removed from the metric together with
+    /// the limiter, once the peer disconnects, which bounds the number of labels.
+    rate_limited: metrics::IntCounterGuard,
+}
+
 pub(crate) struct NetworkState {
     /// Dedicated runtime for `NetworkState` which runs in a separate thread.
     /// Async methods of NetworkState are not cancellable,
@@ -104,7 +132,16 @@ pub(crate) struct NetworkState {
     pub accounts_data: Arc<accounts_data::Cache>,
     /// Connected peers (inbound and outbound) with their full peer information.
     pub tier2: connection::Pool,
-    pub tier1: connection::Pool,
+    /// TIER1 connections are kept in 2 separate pools, depending on which side
+    /// has established the connection. This way a node can keep an outbound connection
+    /// to the address it prefers, even if there is also an inbound connection from the same peer.
+    /// Both ends of a loop connection are kept in tier1_outbound.
+    /// Use tier1_start_outbound() and tier1_insert_ready() to add connections to them.
+    pub tier1_outbound: connection::Pool,
+    pub tier1_inbound: connection::Pool,
+    /// Serializes tier1_start_outbound() and tier1_insert_ready(), which check
+    /// the connections across both TIER1 pools.
+    tier1_pools_mutex: Mutex<()>,
     /// Semaphore limiting inflight inbound handshakes.
     pub inbound_handshake_permits: Arc<tokio::sync::Semaphore>,
     /// Peer store that provides read/write access to peers.
@@ -119,6 +156,10 @@ pub(crate) struct NetworkState {
     /// Hashes of the body of recently received routed messages.
     /// It allows us to determine whether messages arrived faster over TIER1 or TIER2 network.
     pub recent_routed_messages: Mutex<lru::LruCache<CryptoHash, ()>>,
+    /// Hashes of recently received important TIER2 messages addressed to this node.
+    /// Important messages are sent multiple times (possibly over different routes), so
+    /// only the first received copy is handled and the rest are dropped.
+    pub recent_important_messages: Mutex<lru::LruCache<CryptoHash, ()>>,
 
     /// Hash of messages that requires routing back to respective previous hop.
     /// Currently unused, as TIER1 messages do not require a response.
@@ -129,14 +170,38 @@ pub(crate) struct NetworkState {
     /// Note that the route_back table for TIER2 is stored in graph.routing_table_view.
     pub tier1_route_back: Mutex<RouteBackCache>,
 
+    /// Important messages to accounts, which we didn't know how to route to.
+    /// They are re-sent by retry_unroutable_messages() until their retry window expires.
+    pub unroutable_messages: Mutex<RetryBuffer>,
+
+    /// Round trip times to peers, measured via Ping/Pong.
+    pub rtt: Mutex<RttTracker>,
+
     /// Shared counter across all PeerActors, which counts number of `RoutedMessageBody::ForwardTx`
     /// messages sincce last block.
     pub txns_since_last_block: AtomicUsize,
 
+    /// Rate limiters of the routed messages received from each connected peer.
+    /// Each peer has its own budget, so that a single peer cannot starve the others.
+    routed_message_limiters: Mutex<HashMap<PeerId, RoutedMessageLimiter>>,
+
     /// Whitelisted nodes, which are allowed to connect even if the connection limit has been
     /// reached.
     whitelist_nodes: Vec<WhitelistNode>,
 
+    /// Public address of this node, as discovered via STUN servers.
+    /// Set only if the validator proxies are configured as ValidatorProxies::Dynamic.
+    pub tier1_public_addr: ArcSwap<Option<PeerAddr>>,
+
+    /// Backoff of the connection attempts to proxies of other TIER1 nodes, by proxy peer_id.
+    pub tier1_connect_backoff: Mutex<HashMap<PeerId, tier1::ConnectBackoff>>,
+    /// Liveness probing state of the TIER1 connections, by peer_id.
+    pub tier1_liveness: Mutex<HashMap<PeerId, tier1::Liveness>>,
+    /// RNG used to select the TIER1 accounts and proxies to connect to.
+    /// If None, rand::thread_rng() is used. Tests set it to a seeded RNG,
+    /// so that the selection is deterministic.
+    pub tier1_rng: Mutex<Option<Box<dyn rand::RngCore + Send>>>,
+
     /// Mutex which prevents overlapping calls to tier1_advertise_proxies.
     tier1_advertise_proxies_mutex: tokio::sync::Mutex<()>,
     /// Demultiplexer aggregating calls to add_edges().
@@ -157,40 +222,75 @@ impl NetworkState {
         client: Arc<dyn client::Client>,
         shards_manager_adapter: Sender<ShardsManagerRequestFromNetwork>,
         whitelist_nodes: Vec<WhitelistNode>,
-    ) -> Self {
-        Self {
+    ) -> anyhow::Result<Self> {
+        metrics::INBOUND_HANDSHAKE_PERMITS_AVAILABLE.set(LIMIT_PENDING_PEERS as i64);
+        Ok(Self {
             runtime: Runtime::new(),
             graph: Arc::new(crate::routing::Graph::new(
                 crate::routing::GraphConfig {
                     node_id: config.node_id(),
                     prune_unreachable_peers_after: PRUNE_UNREACHABLE_PEERS_AFTER,
+                    prune_interval: None,
+                    max_route_hops: None,
+                    reachability_recovery: None,
                     prune_edges_after: Some(PRUNE_EDGES_AFTER),
+                    max_edges: Some(MAX_GRAPH_EDGES),
+                    edge_history: Some(crate::routing::EdgeHistoryConfig {
+                        len: EDGE_HISTORY_LEN,
+                        all_edges: false,
+                    }),
+                    verify_batch_size: EDGE_VERIFICATION_BATCH_SIZE,
+                    self_check: false,
                 },
                 store.clone(),
-            )),
+            )?),
             genesis_id,
             client,
             shards_manager_adapter,
             chain_info: Default::default(),
-            tier2: connection::Pool::new(config.node_id()),
-            tier1: connection::Pool::new(config.node_id()),
+            tier2: connection::Pool::new(config.node_id(), "tier2"),
+            tier1_outbound: connection::Pool::new(config.node_id(), "tier1_outbound"),
+            tier1_inbound: connection::Pool::new(config.node_id(), "tier1_inbound"),
+            tier1_pools_mutex: Mutex::new(()),
             inbound_handshake_permits: Arc::new(tokio::sync::Semaphore::new(LIMIT_PENDING_PEERS)),
             peer_store,
             connection_store: connection_store::ConnectionStore::new(store).unwrap(),
             pending_reconnect: Mutex::new(Vec::<PeerInfo>::new()),
-            accounts_data: Arc::new(accounts_data::Cache::new()),
-            tier1_route_back: Mutex::new(RouteBackCache::default()),
+            accounts_data: Arc::new(accounts_data::Cache::new(
+                config.tier1.as_ref().map(|cfg| cfg.accounts_data_ttl),
+            )),
+            tier1_route_back: Mutex::new(match &config.tier1 {
+                Some(cfg) => RouteBackCache::new(
+                    cfg.route_back_capacity,
+                    cfg.route_back_ttl,
+                    route_back_cache::DEFAULT_REMOVE_BATCH_SIZE,
+                ),
+                None => RouteBackCache::default(),
+            }),
+            unroutable_messages: Mutex::new(RetryBuffer::new(
+                UNROUTABLE_MESSAGES_CAPACITY,
+                config.unroutable_message_retry_window,
+            )),
             recent_routed_messages: Mutex::new(lru::LruCache::new(
                 RECENT_ROUTED_MESSAGES_CACHE_SIZE,
             )),
+            recent_important_messages: Mutex::new(lru::LruCache::new(
+                RECENT_IMPORTANT_MESSAGES_CACHE_SIZE,
+            )),
+            rtt: Mutex::new(RttTracker::new(PING_TIMEOUT)),
             txns_since_last_block: AtomicUsize::new(0),
+            routed_message_limiters: Mutex::new(HashMap::new()),
             whitelist_nodes,
             add_edges_demux: demux::Demux::new(config.routing_table_update_rate_limit),
             set_chain_info_mutex: Mutex::new(()),
             config,
             created_at: clock.now(),
+            tier1_public_addr: ArcSwap::default(),
+            tier1_connect_backoff: Mutex::new(HashMap::new()),
+            tier1_liveness: Mutex::new(HashMap::new()),
+            tier1_rng: Mutex::new(None),
             tier1_advertise_proxies_mutex: tokio::sync::Mutex::new(()),
-        }
+        })
     }
 
     /// Spawn a future on the runtime which has the same lifetime as the NetworkState instance.
@@ -208,6 +308,37 @@ impl NetworkState {
         self.runtime.handle.spawn(fut.in_current_span())
     }
 
+    /// Checks whether a routed message received from `peer_id` fits into the rate limit of that peer.
+    /// The messages which don't are counted by PEER_RATE_LIMITED.
+    pub fn allow_routed_message(&self, clock: &time::Clock, peer_id: &PeerId) -> bool {
+        let mut limiters = self.routed_message_limiters.lock();
+        let limiter = limiters.entry(peer_id.clone()).or_insert_with(|| RoutedMessageLimiter {
+            limiter: rate::Limiter::new(clock, self.config.peer_routed_message_rate_limit),
+            rate_limited: metrics::MetricGuard::new(
+                &metrics::PEER_RATE_LIMITED,
+                vec![peer_id.to_string()],
+            ),
+        });
+        if !limiter.limiter.allow(clock) {
+            limiter.rate_limited.inc();
+            return false;
+        }
+        true
+    }
+
+    /// Drops the per-peer state (rate limiter, round trip times) of `peer_id`,
+    /// unless the peer is still connected over some tier.
+    fn forget_disconnected_peer(&self, peer_id: &PeerId) {
+        if self.tier1_outbound.load().ready.contains_key(peer_id)
+            || self.tier1_inbound.load().ready.contains_key(peer_id)
+            || self.tier2.load().ready.contains_key(peer_id)
+        {
+            return;
+        }
+        self.routed_message_limiters.lock().remove(peer_id);
+        self.rtt.lock().remove(peer_id);
+    }
+
     /// Stops peer instance if it is still connected,
     /// and then mark peer as banned in the peer store.
     pub fn disconnect_and_ban(
@@ -296,7 +427,7 @@ impl NetworkState {
                     if !edge.verify() {
                         return Err(RegisterPeerError::InvalidEdge);
                     }
-                    this.tier1.insert_ready(conn).map_err(RegisterPeerError::PoolError)?;
+                    this.tier1_insert_ready(conn).map_err(RegisterPeerError::PoolError)?;
                 }
                 tcp::Tier::T2 => {
                     if conn.peer_type == PeerType::Inbound {
@@ -345,10 +476,12 @@ impl NetworkState {
             if conn.tier == tcp::Tier::T1 {
                 // There is no banning or routing table for TIER1.
                 // Just remove the connection from the network_state.
-                this.tier1.remove(&conn);
+                this.tier1_pool(&conn).remove(&conn);
+                this.forget_disconnected_peer(&peer_id);
                 return;
             }
             this.tier2.remove(&conn);
+            this.forget_disconnected_peer(&peer_id);
 
             // If the last edge we have with this peer represent a connection addition, create the edge
             // update that represents the connection removal.
@@ -432,12 +565,12 @@ impl NetworkState {
         }
     }
 
-    #[cfg(test)]
     pub fn send_ping(&self, clock: &time::Clock, tier: tcp::Tier, nonce: u64, target: PeerId) {
         let body = RoutedMessageBody::Ping(crate::network_protocol::Ping {
             nonce,
             source: self.config.node_id(),
         });
+        self.rtt.lock().ping_sent(clock.now(), nonce, target.clone());
         let msg = RawRoutedMessage { target: PeerIdOrHash::PeerId(target), body };
         self.send_message_to_peer(clock, tier, self.sign_message(clock, msg));
     }
@@ -452,11 +585,8 @@ impl NetworkState {
     }
 
     pub fn sign_message(&self, clock: &time::Clock, msg: RawRoutedMessage) -> Box<RoutedMessageV2> {
-        Box::new(msg.sign(
-            &self.config.node_key,
-            self.config.routed_message_ttl,
-            Some(clock.now_utc()),
-        ))
+        let ttl = self.config.routed_message_ttl(&msg.body);
+        Box::new(msg.sign(&self.config.node_key, ttl, Some(clock.now_utc())))
     }
 
     /// Route signed message to target peer.
@@ -490,7 +620,18 @@ impl NetworkState {
                     }
                     PeerIdOrHash::PeerId(peer_id) => peer_id.clone(),
                 };
-                return self.tier1.send_message(peer_id, Arc::new(PeerMessage::Routed(msg)));
+                // Remember if we expect a response for this message, so that
+                // message_for_me() recognizes the response.
+                if msg.author == my_peer_id && msg.expect_response() {
+                    self.graph.routing_table.add_route_back(&clock, msg.hash(), my_peer_id);
+                }
+                // Prefer the connection established by this node.
+                let pool = if self.tier1_outbound.load().ready.contains_key(&peer_id) {
+                    &self.tier1_outbound
+                } else {
+                    &self.tier1_inbound
+                };
+                return pool.send_message(peer_id, Arc::new(PeerMessage::Routed(msg)));
             }
             tcp::Tier::T2 => match self.graph.routing_table.find_route(&clock, &msg.target) {
                 Ok(peer_id) => {
@@ -502,7 +643,8 @@ impl NetworkState {
                     return self.tier2.send_message(peer_id, Arc::new(PeerMessage::Routed(msg)));
                 }
                 Err(find_route_error) => {
-                    // TODO(MarX, #1369): Message is dropped here. Define policy for this case.
+                    // Important messages sent via send_message_to_account() are retried,
+                    // see NetworkState::unroutable_messages.
                     metrics::MessageDropped::NoRouteFound.inc(&msg.body);
 
                     tracing::debug!(target: "network",
@@ -522,12 +664,59 @@ impl NetworkState {
     /// Send message to specific account.
     /// Return whether the message is sent or not.
     /// The message might be sent over TIER1 and/or TIER2 connection depending on the message type.
+    /// If the message is important and cannot be sent right away, it is buffered
+    /// and re-sent by retry_unroutable_messages().
     pub fn send_message_to_account(
         &self,
         clock: &time::Clock,
         account_id: &AccountId,
         msg: RoutedMessageBody,
     ) -> bool {
+        let unsent = match self.try_send_message_to_account(clock, account_id, msg) {
+            Ok(()) => return true,
+            Err(unsent) => unsent,
+        };
+        if tracing::enabled!(target: "network", tracing::Level::DEBUG) {
+            let route = self.explain_route(account_id);
+            tracing::debug!(target: "network",
+                to = ?account_id,
+                owner = ?route.owner,
+                next_hops = ?route.next_hops,
+                tier1 = ?route.tier1,
+                "Failed to send message to account");
+        }
+        if let Some(msg) = unsent {
+            self.unroutable_messages.lock().push(clock.now(), account_id.clone(), msg);
+        }
+        false
+    }
+
+    /// Re-attempts sending the buffered unroutable messages.
+    /// Messages which still cannot be sent are buffered again, until their retry window expires.
+    pub fn retry_unroutable_messages(&self, clock: &time::Clock) {
+        let pending = self.unroutable_messages.lock().take(clock.now());
+        for PendingMessage { deadline, account_id, body } in pending {
+            if let Err(Some(body)) = self.try_send_message_to_account(clock, &account_id, body) {
+                self.unroutable_messages.lock().push_pending(PendingMessage {
+                    deadline,
+                    account_id,
+                    body,
+                });
+            }
+        }
+    }
+
+    /// Sends the message to the account, without buffering it on failure.
+    /// On failure, an important message is returned back to the caller, so that it can be
+    /// retried without copying it upfront. Such a message is not counted as dropped here,
+    /// it is counted once the retries are given up (see RetryBuffer).
+    fn try_send_message_to_account(
+        &self,
+        clock: &time::Clock,
+        account_id: &AccountId,
+        msg: RoutedMessageBody,
+    ) -> Result<(), Option<RoutedMessageBody>> {
+        let retry = msg.is_important();
         let mut success = false;
         let accounts_data = self.accounts_data.load();
         // All TIER1 messages are being sent over both TIER1 and TIER2 connections for now,
@@ -544,14 +733,15 @@ impl NetworkState {
                     Some(conn) => conn,
                     None => continue,
                 };
-                // TODO(gprusak): in case of PartialEncodedChunk, consider stripping everything
-                // but the header. This will bound the message size
+                // Messages relayed by a proxy are stripped to bound their size.
+                let body = if conn.peer_info.id == data.peer_id {
+                    msg.clone()
+                } else {
+                    msg.for_tier1_proxy()
+                };
                 conn.send_message(Arc::new(PeerMessage::Routed(self.sign_message(
                     clock,
-                    RawRoutedMessage {
-                        target: PeerIdOrHash::PeerId(data.peer_id.clone()),
-                        body: msg.clone(),
-                    },
+                    RawRoutedMessage { target: PeerIdOrHash::PeerId(data.peer_id.clone()), body },
                 ))));
                 success |= true;
                 break;
@@ -573,11 +763,12 @@ impl NetworkState {
         let target = if let Some(peer_id) = peer_id_from_account_data {
             metrics::ACCOUNT_TO_PEER_LOOKUPS.with_label_values(&["AccountData"]).inc();
             peer_id
-        } else if let Some(peer_id) = self.graph.routing_table.account_owner(account_id) {
+        } else if let Some(peer_id) = self.graph.routing_table.account_owner(clock, account_id) {
             metrics::ACCOUNT_TO_PEER_LOOKUPS.with_label_values(&["AnnounceAccount"]).inc();
             peer_id
+        } else if retry {
+            return Err(Some(msg));
         } else {
-            // TODO(MarX, #1369): Message is dropped here. Define policy for this case.
             metrics::MessageDropped::UnknownAccount.inc(&msg);
             tracing::debug!(target: "network",
                    account_id = ?self.config.validator.as_ref().map(|v|v.account_id()),
@@ -585,19 +776,34 @@ impl NetworkState {
                    ?msg,"Drop message: unknown account",
             );
             tracing::trace!(target: "network", known_peers = ?self.graph.routing_table.get_accounts_keys(), "Known peers");
-            return false;
+            return Err(None);
         };
 
+        if retry
+            && !success
+            && self.graph.routing_table.view_route(&target).map_or(true, |hops| hops.is_empty())
+        {
+            // The message will be retried, so send_message_to_peer() shouldn't count it
+            // as dropped.
+            return Err(Some(msg));
+        }
         let msg = RawRoutedMessage { target: PeerIdOrHash::PeerId(target), body: msg };
         let msg = self.sign_message(clock, msg);
-        if msg.body.is_important() {
-            for _ in 0..IMPORTANT_MESSAGE_RESENT_COUNT {
+        if retry {
+            for _ in 0..self.config.important_message_resent_count {
                 success |= self.send_message_to_peer(clock, tcp::Tier::T2, msg.clone());
             }
+            if !success {
+                return Err(Some(msg.msg.body));
+            }
         } else {
             success |= self.send_message_to_peer(clock, tcp::Tier::T2, msg)
         }
-        success
+        if success {
+            Ok(())
+        } else {
+            Err(None)
+        }
     }
 
     pub async fn add_accounts_data(
@@ -703,6 +909,26 @@ impl NetworkState {
         .unwrap()
     }
 
+    /// Resets the number of ForwardTx messages received since the last block.
+    /// Should be called whenever a block arrives.
+    pub fn reset_txns_since_last_block(&self) {
+        self.txns_since_last_block.store(0, Ordering::Release);
+        metrics::TXNS_SINCE_LAST_BLOCK.set(0);
+    }
+
+    /// Registers a received ForwardTx message.
+    /// Returns false iff `max_forward_txs_per_block` has been already reached,
+    /// in which case the message should be dropped.
+    pub fn register_forward_tx(&self) -> bool {
+        let txns = self.txns_since_last_block.load(Ordering::Acquire);
+        if self.config.max_forward_txs_per_block.map_or(false, |max| txns >= max) {
+            return false;
+        }
+        let txns = self.txns_since_last_block.fetch_add(1, Ordering::AcqRel) + 1;
+        metrics::TXNS_SINCE_LAST_BLOCK.set(txns as i64);
+        true
+    }
+
     pub fn update_connection_store(self: &Arc<Self>, clock: &time::Clock) {
         self.connection_store.update(clock, &self.tier2.load());
     }
@@ -730,6 +956,11 @@ impl NetworkState {
         // there will be no race condition between subsequent SetChainInfo
         // calls.
         self.chain_info.store(Arc::new(Some(info.clone())));
+        let header = info.block.header();
+        self.graph.routing_table.set_live_epochs(HashSet::from([
+            header.epoch_id().clone(),
+            header.next_epoch_id().clone(),
+        ]));
 
         // If tier1 is not enabled, we skip set_keys() call.
         // This way self.state.accounts_data is always empty, hence no data
@@ -5,8 +5,38 @@ use crate::stats::metrics;
 use crate::types::ReasonForBan;
 use near_async::time;
 use near_primitives::network::{AnnounceAccount, PeerId};
+use near_primitives::types::AccountId;
 use std::sync::Arc;
 
+/// Source of the account owner, see `NetworkState::explain_route`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AccountOwnerSource {
+    AccountData,
+    AnnounceAccount,
+}
+
+/// TIER1 connection over which a message to an account would be sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Tier1Route {
+    /// Direct connection to the account owner.
+    Direct,
+    /// Connection to a proxy of the account owner.
+    Proxy(PeerId),
+}
+
+/// Explains how a message to an account would be routed, see `NetworkState::explain_route`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct RouteExplanation {
+    /// Owner of the account and where it has been learned from.
+    /// None if the account is unknown.
+    pub owner: Option<(PeerId, AccountOwnerSource)>,
+    /// TIER2 next hops towards the owner. Empty if the owner is unknown or unreachable.
+    pub next_hops: Vec<PeerId>,
+    /// TIER1 connection to the owner or its proxy, if available.
+    /// Note that only some messages are allowed to be sent over TIER1.
+    pub tier1: Option<Tier1Route>,
+}
+
 impl NetworkState {
     // TODO(gprusak): eventually, this should be blocking, as it should be up to the caller
     // whether to wait for the broadcast to finish, or run it in parallel with sth else.
@@ -23,10 +53,15 @@ impl NetworkState {
 
     /// Adds AnnounceAccounts (without validating them) to the routing table.
     /// Then it broadcasts all the AnnounceAccounts that haven't been seen before.
-    pub async fn add_accounts(self: &Arc<NetworkState>, accounts: Vec<AnnounceAccount>) {
+    pub async fn add_accounts(
+        self: &Arc<NetworkState>,
+        clock: &time::Clock,
+        accounts: Vec<AnnounceAccount>,
+    ) {
         let this = self.clone();
+        let clock = clock.clone();
         self.spawn(async move {
-            let new_accounts = this.graph.routing_table.add_accounts(accounts);
+            let new_accounts = this.graph.routing_table.add_accounts(&clock, accounts);
             tracing::debug!(target: "network", account_id = ?this.config.validator.as_ref().map(|v|v.account_id()), ?new_accounts, "Received new accounts");
             this.broadcast_routing_table_update(RoutingTableUpdate::from_accounts(
                 new_accounts.clone(),
@@ -35,6 +70,54 @@ impl NetworkState {
         }).await.unwrap()
     }
 
+    /// Drops the AnnounceAccounts which haven't been refreshed in time and
+    /// rebroadcasts the AnnounceAccounts of this node, before they expire.
+    /// We call this function every REFRESH_ACCOUNTS_INTERVAL from peer_manager_actor.rs.
+    pub fn refresh_accounts(&self, clock: &time::Clock) {
+        let expired = self.graph.routing_table.expire_accounts(clock);
+        if expired > 0 {
+            tracing::debug!(target: "network", expired, "Dropped expired accounts");
+        }
+        let accounts = self.graph.routing_table.refresh_own_accounts(clock, &self.config.node_id());
+        self.broadcast_routing_table_update(RoutingTableUpdate::from_accounts(accounts));
+    }
+
+    /// Reports how a message to `account_id` would be routed by send_message_to_account(),
+    /// without any side effects. In particular, the AnnounceAccounts are looked up only
+    /// in the routing table snapshot (without loading them from storage).
+    pub fn explain_route(&self, account_id: &AccountId) -> RouteExplanation {
+        let accounts_data = self.accounts_data.load();
+        let data: Vec<_> = accounts_data
+            .keys_by_id
+            .get(account_id)
+            .iter()
+            .flat_map(|keys| keys.iter())
+            .flat_map(|key| accounts_data.data.get(key))
+            .collect();
+        let tier1 = data.iter().find_map(|data| {
+            let conn = self.get_tier1_proxy(data)?;
+            Some(match conn.peer_info.id == data.peer_id {
+                true => Tier1Route::Direct,
+                false => Tier1Route::Proxy(conn.peer_info.id.clone()),
+            })
+        });
+        let owner = match data.first() {
+            Some(data) => Some((data.peer_id.clone(), AccountOwnerSource::AccountData)),
+            None => self
+                .graph
+                .routing_table
+                .snapshot()
+                .account_owners
+                .get(account_id)
+                .map(|peer_id| (peer_id.clone(), AccountOwnerSource::AnnounceAccount)),
+        };
+        let next_hops = owner
+            .as_ref()
+            .and_then(|(peer_id, _)| self.graph.routing_table.view_route(peer_id))
+            .unwrap_or_default();
+        RouteExplanation { owner, next_hops, tier1 }
+    }
+
     /// Constructs a partial edge to the given peer with the nonce specified.
     /// If nonce is None, nonce is selected automatically.
     pub fn propose_edge(
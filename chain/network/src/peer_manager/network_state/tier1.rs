@@ -5,6 +5,7 @@ use crate::network_protocol::{
 };
 use crate::peer::peer_actor::PeerActor;
 use crate::peer_manager::connection;
+use crate::stats::metrics;
 use crate::stun;
 use crate::tcp;
 use crate::types::PeerType;
@@ -17,6 +18,34 @@ use rand::seq::SliceRandom as _;
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
+/// Backoff of the connection attempts to a proxy of another TIER1 node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectBackoff {
+    /// Number of consecutive failed attempts.
+    pub failures: u32,
+    /// No new attempt is made before this time.
+    pub next_retry_at: time::Instant,
+}
+
+impl ConnectBackoff {
+    /// Registers a failed attempt at `now`, doubling the delay until the next attempt.
+    fn failed(&mut self, now: time::Instant, cfg: &config::Tier1) {
+        self.failures += 1;
+        // Exponent is capped to avoid an overflow. Delay is capped by config anyway.
+        let delay = cfg.connect_backoff_min * 2i32.pow(self.failures.min(30) - 1);
+        self.next_retry_at = now + std::cmp::min(delay, cfg.connect_backoff_max);
+    }
+}
+
+/// Liveness probing state of a TIER1 connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Liveness {
+    /// Time when the last liveness Ping has been sent.
+    pub ping_sent_at: time::Instant,
+    /// Number of consecutive liveness Pings which haven't been answered.
+    pub missed_pongs: u32,
+}
+
 impl super::NetworkState {
     // Returns ValidatorConfig of this node iff it belongs to TIER1 according to `accounts_data`.
     pub fn tier1_validator_config(
@@ -32,19 +61,99 @@ impl super::NetworkState {
             .filter(|cfg| accounts_data.keys.contains(&cfg.signer.public_key()))
     }
 
+    /// Returns the TIER1 pool which `conn` belongs to.
+    pub fn tier1_pool(&self, conn: &connection::Connection) -> &connection::Pool {
+        // The inbound end of a loop connection is kept together with the outbound end.
+        if conn.peer_type == PeerType::Outbound || conn.peer_info.id == self.config.node_id() {
+            &self.tier1_outbound
+        } else {
+            &self.tier1_inbound
+        }
+    }
+
+    /// Reserves an OutboundHandshakePermit for a TIER1 connection to `peer_id`.
+    /// An inbound connection from `peer_id` doesn't prevent it, so that the node can
+    /// connect to the address of `peer_id` it prefers.
+    pub fn tier1_start_outbound(
+        &self,
+        peer_id: PeerId,
+    ) -> Result<connection::OutboundHandshakePermit, connection::PoolError> {
+        let _lock = self.tier1_pools_mutex.lock();
+        self.tier1_outbound.start_outbound(peer_id)
+    }
+
+    /// Adds a TIER1 connection to the pool it belongs to (see `tier1_pool()`).
+    /// There is at most 1 connection per direction per peer, which Pool::insert_ready()
+    /// enforces on its own. Here the checks which involve both pools are done:
+    /// * the tie between 2 nodes connecting to each other at the same time is broken,
+    /// * at most 1 peer per account key is allowed.
+    pub fn tier1_insert_ready(
+        &self,
+        conn: Arc<connection::Connection>,
+    ) -> Result<(), connection::PoolError> {
+        let _lock = self.tier1_pools_mutex.lock();
+        let pool = self.tier1_pool(&conn);
+        // Both ends of a loop connection are in tier1_outbound, so Pool::insert_ready()
+        // handles them on its own.
+        if conn.peer_info.id == self.config.node_id() {
+            return pool.insert_ready(conn);
+        }
+        let id = &conn.peer_info.id;
+        let other = match conn.peer_type {
+            PeerType::Inbound => {
+                // The outbound handshakes are tracked by tier1_outbound, so the tie
+                // between 2 nodes connecting to each other at the same time is broken here,
+                // the same way Pool::insert_ready() does it for a single pool.
+                let outbound = self.tier1_outbound.load();
+                if outbound.outbound_handshakes.contains(id) && id >= &outbound.me {
+                    return Err(connection::PoolError::AlreadyStartedConnecting);
+                }
+                outbound
+            }
+            PeerType::Outbound => self.tier1_inbound.load(),
+        };
+        if let Some(owned_account) = &conn.owned_account {
+            match other.ready_by_account_key.get(&owned_account.account_key) {
+                // A connection in the other direction to the same peer is fine.
+                Some(other) if &other.peer_info.id != id => {
+                    let err = connection::PoolError::AlreadyConnectedAccount {
+                        peer_id: other.peer_info.id.clone(),
+                        account_key: owned_account.account_key.clone(),
+                    };
+                    tracing::warn!(target:"network", "tier1_insert_ready({id}): {err}");
+                    metrics::ALREADY_CONNECTED_ACCOUNT.inc();
+                    return Err(err);
+                }
+                _ => {}
+            }
+        }
+        pool.insert_ready(conn)
+    }
+
+    /// Returns the proxies that this node should connect to, i.e. those which are
+    /// neither connected nor have an outbound handshake in progress.
+    /// Inbound connections from the proxies are not taken into account, because they
+    /// are not necessarily established to the address from the config.
+    pub(crate) fn tier1_missing_proxies(&self, proxies: &[PeerAddr]) -> Vec<PeerAddr> {
+        let tier1 = self.tier1_outbound.load();
+        proxies
+            .iter()
+            .filter(|proxy| {
+                !tier1.ready.contains_key(&proxy.peer_id)
+                    && !tier1.outbound_handshakes.contains(&proxy.peer_id)
+            })
+            .cloned()
+            .collect()
+    }
+
     async fn tier1_connect_to_my_proxies(
         self: &Arc<Self>,
         clock: &time::Clock,
         proxies: &[PeerAddr],
     ) {
-        let tier1 = self.tier1.load();
         // Try to connect to all proxies in parallel.
         let mut handles = vec![];
-        for proxy in proxies {
-            // Skip the proxies we are already connected to.
-            if tier1.ready.contains_key(&proxy.peer_id) {
-                continue;
-            }
+        for proxy in self.tier1_missing_proxies(proxies) {
             handles.push(async move {
                 let res = async {
                     let stream = tcp::Stream::connect(
@@ -77,70 +186,11 @@ impl super::NetworkState {
         })));
     }
 
-    /// Tries to connect to ALL trusted proxies from the config, then broadcasts AccountData with
-    /// the set of proxies it managed to connect to. This way other TIER1 nodes can just connect
-    /// to ANY proxy of this node.
-    pub async fn tier1_advertise_proxies(
-        self: &Arc<Self>,
-        clock: &time::Clock,
-    ) -> Option<Arc<SignedAccountData>> {
-        // Tier1 advertise proxies calls should be disjoint,
-        // to avoid a race condition while connecting to the proxies.
-        // TODO(gprusak): there are more corner cases to cover, because
-        // tier1_connect may also spawn TIER1 connections conflicting with
-        // tier1_advertise_proxies. It would be better to be able to await
-        // handshake on connection attempts, even if another call spawned them.
-        let _lock = self.tier1_advertise_proxies_mutex.lock().await;
-        let accounts_data = self.accounts_data.load();
-
-        let vc = self.tier1_validator_config(&accounts_data)?;
-        let proxies = match (&self.config.node_addr, &vc.proxies) {
-            (None, _) => vec![],
-            (_, config::ValidatorProxies::Static(peer_addrs)) => peer_addrs.clone(),
-            // If Dynamic are specified,
-            // it means that this node is its own proxy.
-            // Discover the public IP of this node using those STUN servers.
-            // We do not require all stun servers to be available, but
-            // we require the received responses to be consistent.
-            (Some(node_addr), config::ValidatorProxies::Dynamic(stun_servers)) => {
-                // Query all the STUN servers in parallel.
-                let queries = stun_servers.iter().map(|addr| {
-                    let clock = clock.clone();
-                    let addr = addr.clone();
-                    self.spawn(async move {
-                        match stun::query(&clock, &addr).await {
-                            Ok(ip) => Some(ip),
-                            Err(err) => {
-                                tracing::warn!(target:"network", "STUN lookup failed for {addr}: {err}");
-                                None
-                            }
-                        }
-                    })
-                });
-                let mut node_ips = vec![];
-                for q in queries {
-                    node_ips.extend(q.await.unwrap());
-                }
-                // Check that we have received non-zero responses and that they are consistent.
-                if node_ips.is_empty() {
-                    vec![]
-                } else if !node_ips.iter().all(|ip| ip == &node_ips[0]) {
-                    tracing::warn!(target:"network", "received inconsistent responses from the STUN servers");
-                    vec![]
-                } else {
-                    vec![PeerAddr {
-                        peer_id: self.config.node_id(),
-                        addr: std::net::SocketAddr::new(node_ips[0], node_addr.port()),
-                    }]
-                }
-            }
-        };
-        self.tier1_connect_to_my_proxies(clock, &proxies).await;
-
-        // Snapshot tier1 connections again before broadcasting.
-        let tier1 = self.tier1.load();
-
-        let my_proxies = match &vc.proxies {
+    /// Returns the proxies of this node, which can be advertised to other TIER1 nodes,
+    /// i.e. the configured proxies that this node has an established TIER1 connection to.
+    pub fn my_tier1_proxies(&self, vc: &config::ValidatorConfig) -> Vec<PeerAddr> {
+        let tier1 = self.tier1_outbound.load();
+        match &vc.proxies {
             // In case of dynamic configuration, only the node itself can be its proxy,
             // so we look for a loop connection which would prove our node's address.
             config::ValidatorProxies::Dynamic(_) => match tier1.ready.get(&self.config.node_id()) {
@@ -164,14 +214,9 @@ impl super::NetworkState {
                         // TCP socket is connected + port indicated by the peer).
                         // We will broadcast only those addresses which we confirmed are
                         // valid (i.e. we managed to connect to them).
-                        //
-                        // TODO(gprusak): It may happen that a single peer will be
-                        // available under multiple IPs, in which case, we should
-                        // prefer to connect to the IP from the config, however
-                        // that would require having separate inbound and outbound
-                        // pools, so that both endpoints can keep a connection
-                        // to the IP that they prefer. This is a corner case which can happen
-                        // only if 2 TIER1 validators are proxies for some other validator.
+                        // Only outbound connections are taken into account, so that
+                        // an inbound connection from the proxy (which might come from a
+                        // different IP) doesn't prevent us from connecting to the IP from the config.
                         Some(conn) if conn.peer_info.addr == Some(proxy.addr) => {
                             connected_proxies.push(proxy.clone());
                         }
@@ -183,18 +228,120 @@ impl super::NetworkState {
                 }
                 connected_proxies
             }
+        }
+    }
+
+    /// Discovers the public address of this node using the STUN servers from the config
+    /// and stores it in tier1_public_addr. It is a no-op unless the validator proxies
+    /// are configured as ValidatorProxies::Dynamic.
+    /// In case of failure, the last known address is kept.
+    /// Returns the current public address of this node.
+    pub async fn tier1_resolve_public_addr(
+        self: &Arc<Self>,
+        clock: &time::Clock,
+    ) -> Option<PeerAddr> {
+        let vc = self.config.validator.as_ref()?;
+        let (node_addr, stun_servers) = match (&self.config.node_addr, &vc.proxies) {
+            (Some(node_addr), config::ValidatorProxies::Dynamic(stun_servers)) => {
+                (node_addr, stun_servers)
+            }
+            _ => return None,
         };
+        // We do not require all stun servers to be available, but
+        // we require the received responses to be consistent.
+        // Query all the STUN servers in parallel.
+        let queries = stun_servers.iter().map(|addr| {
+            let clock = clock.clone();
+            let addr = addr.clone();
+            self.spawn(async move {
+                match stun::query(&clock, &addr).await {
+                    Ok(ip) => Some(ip),
+                    Err(err) => {
+                        tracing::warn!(target:"network", "STUN lookup failed for {addr}: {err}");
+                        None
+                    }
+                }
+            })
+        });
+        let mut node_ips = vec![];
+        for q in queries {
+            node_ips.extend(q.await.unwrap());
+        }
+        // Check that we have received non-zero responses and that they are consistent.
+        if node_ips.is_empty() {
+            tracing::warn!(target:"network", "all STUN lookups failed, keeping the last known public address");
+        } else if !node_ips.iter().all(|ip| ip == &node_ips[0]) {
+            tracing::warn!(target:"network", "received inconsistent responses from the STUN servers, keeping the last known public address");
+        } else {
+            self.tier1_public_addr.store(Arc::new(Some(PeerAddr {
+                peer_id: self.config.node_id(),
+                addr: std::net::SocketAddr::new(node_ips[0], node_addr.port()),
+            })));
+        }
+        self.tier1_public_addr.load().as_ref().clone()
+    }
+
+    /// Tries to connect to ALL trusted proxies from the config, then broadcasts AccountData with
+    /// the set of proxies it managed to connect to. This way other TIER1 nodes can just connect
+    /// to ANY proxy of this node.
+    pub async fn tier1_advertise_proxies(
+        self: &Arc<Self>,
+        clock: &time::Clock,
+    ) -> Option<Arc<SignedAccountData>> {
+        // Tier1 advertise proxies calls should be disjoint,
+        // to avoid a race condition while connecting to the proxies.
+        // TODO(gprusak): there are more corner cases to cover, because
+        // tier1_connect may also spawn TIER1 connections conflicting with
+        // tier1_advertise_proxies. It would be better to be able to await
+        // handshake on connection attempts, even if another call spawned them.
+        let _lock = self.tier1_advertise_proxies_mutex.lock().await;
+        let accounts_data = self.accounts_data.load();
+
+        let vc = self.tier1_validator_config(&accounts_data)?;
+        let tier1_cfg = self.config.tier1.as_ref()?;
+        let proxies = match (&self.config.node_addr, &vc.proxies) {
+            (None, _) => vec![],
+            (_, config::ValidatorProxies::Static(peer_addrs)) => peer_addrs.clone(),
+            // If Dynamic are specified,
+            // it means that this node is its own proxy.
+            // Use the public address resolved periodically via STUN,
+            // or resolve it now, if it hasn't been resolved yet.
+            (Some(_), config::ValidatorProxies::Dynamic(_)) => {
+                let addr = match self.tier1_public_addr.load().as_ref().clone() {
+                    Some(addr) => Some(addr),
+                    None => self.tier1_resolve_public_addr(clock).await,
+                };
+                addr.into_iter().collect()
+            }
+        };
+        self.tier1_connect_to_my_proxies(clock, &proxies).await;
+
+        // Snapshot tier1 connections again before broadcasting.
+        let my_proxies = self.my_tier1_proxies(vc);
         tracing::info!(target:"network","connected to proxies {my_proxies:?}");
+        let my_data = AccountData { peer_id: self.config.node_id(), proxies: my_proxies };
+        // Skip the broadcast if the data hasn't changed since this node has recently signed it.
+        // Nodes which haven't received it yet will get it during a full sync.
+        let current = self.accounts_data.load();
+        let is_local = current.local.as_ref().map_or(false, |l| *l.data == my_data);
+        if let Some(d) = current.data.get(&vc.signer.public_key()).filter(|_| is_local) {
+            let age = clock.now_utc() - d.timestamp;
+            if d.data == my_data
+                && (time::Duration::ZERO..tier1_cfg.advertise_proxies_refresh_interval)
+                    .contains(&age)
+            {
+                metrics::TIER1_PROXIES_ADVERTISEMENTS.with_label_values(&["skipped"]).inc();
+                return Some(d.clone());
+            }
+        }
         let new_data = self.accounts_data.set_local(
             clock,
-            accounts_data::LocalData {
-                signer: vc.signer.clone(),
-                data: Arc::new(AccountData { peer_id: self.config.node_id(), proxies: my_proxies }),
-            },
+            accounts_data::LocalData { signer: vc.signer.clone(), data: Arc::new(my_data) },
         );
         // Early exit in case this node is not a TIER1 node any more.
         let new_data = new_data?;
         // Advertise the new_data.
+        metrics::TIER1_PROXIES_ADVERTISEMENTS.with_label_values(&["sent"]).inc();
         self.tier2.broadcast_message(Arc::new(PeerMessage::SyncAccountsData(SyncAccountsData {
             incremental: true,
             requesting_full_sync: false,
@@ -203,6 +350,14 @@ impl super::NetworkState {
         Some(new_data)
     }
 
+    /// Calls f with the RNG for the TIER1 connection logic.
+    fn with_tier1_rng<T>(&self, f: impl FnOnce(&mut dyn rand::RngCore) -> T) -> T {
+        match &mut *self.tier1_rng.lock() {
+            Some(rng) => f(rng.as_mut()),
+            None => f(&mut rand::thread_rng()),
+        }
+    }
+
     /// Closes TIER1 connections from nodes which are not TIER1 any more.
     /// If this node is TIER1, it additionally connects to proxies of other TIER1 nodes.
     pub async fn tier1_connect(self: &Arc<Self>, clock: &time::Clock) {
@@ -225,10 +380,16 @@ impl super::NetworkState {
                 accounts_by_proxy.entry(&p.peer_id).or_default().push(&d.account_key);
             }
         }
+        // Forget the backoff of peers which are not proxies any more.
+        self.tier1_connect_backoff
+            .lock()
+            .retain(|peer_id, _| accounts_by_proxy.contains_key(peer_id));
 
         // Browse the connections from newest to oldest.
-        let tier1 = self.tier1.load();
-        let mut ready: Vec<_> = tier1.ready.values().collect();
+        let tier1_outbound = self.tier1_outbound.load();
+        let tier1_inbound = self.tier1_inbound.load();
+        let mut ready: Vec<_> =
+            tier1_outbound.ready.values().chain(tier1_inbound.ready.values()).collect();
         ready.sort_unstable_by_key(|c| c.established_time);
         ready.reverse();
 
@@ -250,7 +411,11 @@ impl super::NetworkState {
                 }
                 // Direct TIER1 connections have priority over proxy connections.
                 for key in &accounts_data.keys {
-                    if let Some(conn) = tier1.ready_by_account_key.get(&key) {
+                    if let Some(conn) = tier1_outbound
+                        .ready_by_account_key
+                        .get(&key)
+                        .or_else(|| tier1_inbound.ready_by_account_key.get(&key))
+                    {
                         safe.insert(key, &conn.peer_info.id);
                     }
                 }
@@ -259,10 +424,8 @@ impl super::NetworkState {
             // (to act as a TIER1 proxy).
             None => {
                 for key in &accounts_data.keys {
-                    if let Some(conn) = tier1.ready_by_account_key.get(&key) {
-                        if conn.peer_type == PeerType::Inbound {
-                            safe.insert(key, &conn.peer_info.id);
-                        }
+                    if let Some(conn) = tier1_inbound.ready_by_account_key.get(&key) {
+                        safe.insert(key, &conn.peer_info.id);
                     }
                 }
             }
@@ -285,8 +448,9 @@ impl super::NetworkState {
             }
         }
         // Close all other connections, as they are redundant or are no longer TIER1.
-        for conn in tier1.ready.values() {
+        for conn in &ready {
             if !safe_set.contains(&conn.peer_info.id) {
+                metrics::TIER1_CONNECTIONS_CLOSED.inc();
                 conn.stop(None);
             }
         }
@@ -294,7 +458,9 @@ impl super::NetworkState {
             // Try to establish new TIER1 connections to accounts in random order.
             let mut handles = vec![];
             let mut account_keys: Vec<_> = proxies_by_account.keys().copied().collect();
-            account_keys.shuffle(&mut rand::thread_rng());
+            // Sort first, so that the order depends only on the RNG.
+            account_keys.sort();
+            self.with_tier1_rng(|rng| account_keys.shuffle(rng));
             for account_key in account_keys {
                 // tier1_connect() is responsible for connecting to proxies
                 // of this node. tier1_connect() connects only to proxies
@@ -312,53 +478,161 @@ impl super::NetworkState {
                 if safe.contains_key(account_key) {
                     continue;
                 }
-                // Find addresses of proxies of account_key.
-                let proxies: Vec<&PeerAddr> =
-                    proxies_by_account.get(account_key).into_iter().flatten().map(|x| *x).collect();
+                // Find addresses of proxies of account_key, skipping those in backoff.
+                let now = clock.now();
+                let proxies: Vec<&PeerAddr> = {
+                    let backoff = self.tier1_connect_backoff.lock();
+                    proxies_by_account
+                        .get(account_key)
+                        .into_iter()
+                        .flatten()
+                        .map(|x| *x)
+                        .filter(|p| {
+                            backoff.get(&p.peer_id).map_or(true, |b| b.next_retry_at <= now)
+                        })
+                        .collect()
+                };
                 // Select a random proxy of the account_key and try to connect to it.
-                let proxy = proxies.iter().choose(&mut rand::thread_rng());
+                let proxy = self.with_tier1_rng(|rng| proxies.iter().choose(rng));
                 if let Some(proxy) = proxy {
                     let proxy = (*proxy).clone();
                     handles.push(async move {
-                        let stream = tcp::Stream::connect(
-                            &PeerInfo {
-                                id: proxy.peer_id,
-                                addr: Some(proxy.addr),
-                                account_id: None,
-                            },
-                            tcp::Tier::T1,
-                        )
-                        .await?;
-                        PeerActor::spawn_and_handshake(clock.clone(), stream, None, self.clone())
+                        let res = async {
+                            let stream = tcp::Stream::connect(
+                                &PeerInfo {
+                                    id: proxy.peer_id.clone(),
+                                    addr: Some(proxy.addr),
+                                    account_id: None,
+                                },
+                                tcp::Tier::T1,
+                            )
+                            .await?;
+                            PeerActor::spawn_and_handshake(
+                                clock.clone(),
+                                stream,
+                                None,
+                                self.clone(),
+                            )
                             .await
+                        }
+                        .await;
+                        (proxy.peer_id, res)
                     });
                 }
             }
             tracing::debug!(target:"network","{}: establishing {} new connections",self.config.node_id(),handles.len());
-            for res in futures_util::future::join_all(handles).await {
-                if let Err(err) = res {
-                    tracing::info!(target:"network", ?err, "{}: failed to establish a TIER1 connection",self.config.node_id());
+            for (peer_id, res) in futures_util::future::join_all(handles).await {
+                let mut backoff = self.tier1_connect_backoff.lock();
+                match res {
+                    Ok(_) => {
+                        backoff.remove(&peer_id);
+                    }
+                    Err(err) => {
+                        metrics::TIER1_CONNECT_FAILURES.inc();
+                        let now = clock.now();
+                        backoff
+                            .entry(peer_id)
+                            .or_insert(ConnectBackoff { failures: 0, next_retry_at: now })
+                            .failed(now, tier1_cfg);
+                        tracing::info!(target:"network", ?err, "{}: failed to establish a TIER1 connection",self.config.node_id());
+                    }
                 }
             }
             tracing::debug!(target:"network","{}: establishing new connections DONE",self.config.node_id());
         }
     }
 
+    /// Drops the AccountData which hasn't been refreshed within `accounts_data_ttl`,
+    /// so that we don't keep connecting to the proxies of TIER1 nodes which went offline.
+    pub fn tier1_expire_accounts_data(&self, clock: &time::Clock) {
+        let expired = self.accounts_data.expire(clock);
+        if expired > 0 {
+            tracing::debug!(target: "network", expired, "Dropped expired AccountData");
+            metrics::ACCOUNTS_DATA_EXPIRED.inc_by(expired as u64);
+        }
+    }
+
+    /// Sends a liveness Ping to every TIER1 peer and closes the connections to peers
+    /// which haven't answered `liveness_max_missed_pongs` consecutive Pings.
+    /// Otherwise a half-open connection would linger until something else notices.
+    /// Round trip times of the answered Pings are recorded in `self.rtt`.
+    pub fn tier1_check_liveness(&self, clock: &time::Clock) {
+        let tier1_cfg = match &self.config.tier1 {
+            Some(it) => it,
+            None => return,
+        };
+        let now = clock.now();
+        // Messages are sent over the outbound connection if both are available, so that is
+        // the one being probed.
+        let mut conns = HashMap::new();
+        for tier1 in [self.tier1_outbound.load(), self.tier1_inbound.load()] {
+            for (peer_id, conn) in &tier1.ready {
+                conns.entry(peer_id.clone()).or_insert(conn.clone());
+            }
+        }
+        let mut to_ping = vec![];
+        let mut to_stop = vec![];
+        {
+            let rtt = self.rtt.lock();
+            let mut liveness = self.tier1_liveness.lock();
+            // Forget the state of peers which are not connected any more.
+            liveness.retain(|peer_id, _| conns.contains_key(peer_id));
+            for (peer_id, conn) in conns {
+                if let Some(l) = liveness.get_mut(&peer_id) {
+                    if rtt.last_pong(&peer_id).map_or(false, |t| t >= l.ping_sent_at) {
+                        l.missed_pongs = 0;
+                    } else {
+                        l.missed_pongs += 1;
+                    }
+                    if l.missed_pongs >= tier1_cfg.liveness_max_missed_pongs {
+                        liveness.remove(&peer_id);
+                        to_stop.push(conn);
+                        continue;
+                    }
+                    l.ping_sent_at = now;
+                } else {
+                    liveness
+                        .insert(peer_id.clone(), Liveness { ping_sent_at: now, missed_pongs: 0 });
+                }
+                to_ping.push(peer_id);
+            }
+        }
+        for conn in to_stop {
+            tracing::info!(target:"network", peer_id = ?conn.peer_info.id, "Closing TIER1 connection: missed too many liveness pongs");
+            metrics::TIER1_LIVENESS_DROPS.inc();
+            conn.stop(None);
+        }
+        for peer_id in to_ping {
+            self.send_ping(clock, tcp::Tier::T1, rand::random(), peer_id);
+        }
+    }
+
     /// Finds a TIER1 connection for the given SignedAccountData.
+    /// A direct connection is returned if available. Otherwise a connection to a proxy
+    /// of the account is returned, but only if this node is a TIER1 validator:
+    /// non-TIER1 nodes (for example nodes which were TIER1 in the previous epoch and still
+    /// have some stale TIER1 connections) are not allowed to send messages via proxies.
     /// It is expected to perform <10 lookups total on average,
     /// so the call latency should be negligible wrt sending a TCP packet.
     // TODO(gprusak): If not, consider precomputing the AccountKey -> Connection mapping.
     pub fn get_tier1_proxy(&self, data: &SignedAccountData) -> Option<Arc<connection::Connection>> {
-        let tier1 = self.tier1.load();
+        // Outbound connections are preferred over inbound ones.
+        let pools = [self.tier1_outbound.load(), self.tier1_inbound.load()];
         // Prefer direct connections.
-        if let Some(conn) = tier1.ready_by_account_key.get(&data.account_key) {
-            return Some(conn.clone());
+        for tier1 in &pools {
+            if let Some(conn) = tier1.ready_by_account_key.get(&data.account_key) {
+                return Some(conn.clone());
+            }
         }
         // In case there is no direct connection and our node is a TIER1 validator, use a proxy.
-        // TODO(gprusak): add a check that our node is actually a TIER1 validator.
+        if self.tier1_validator_config(&self.accounts_data.load()).is_none() {
+            return None;
+        }
         for proxy in &data.proxies {
-            if let Some(conn) = tier1.ready.get(&proxy.peer_id) {
-                return Some(conn.clone());
+            for tier1 in &pools {
+                if let Some(conn) = tier1.ready.get(&proxy.peer_id) {
+                    return Some(conn.clone());
+                }
             }
         }
         None
@@ -28,14 +28,15 @@ use near_performance_metrics_macros::perf;
 use near_primitives::block::GenesisId;
 use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::views::{
-    ConnectionInfoView, EdgeView, KnownPeerStateView, NetworkGraphView, PeerStoreView,
+    ConnectionInfoView, EdgeHistoryView, EdgeTransitionView, EdgeView, KnownPeerStateView,
+    LatencyStatsView, NetworkGraphView, PeerAccountsView, PeerStoreView,
     RecentOutboundConnectionsView,
 };
 use rand::seq::IteratorRandom;
 use rand::thread_rng;
 use rand::Rng;
 use std::cmp::min;
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use tracing::Instrument as _;
@@ -50,6 +51,9 @@ const FIX_LOCAL_EDGES_INTERVAL: time::Duration = time::Duration::seconds(60);
 /// How much time we give fix_local_edges() to resolve the discrepancies, before forcing disconnect.
 const FIX_LOCAL_EDGES_TIMEOUT: time::Duration = time::Duration::seconds(6);
 
+/// How often to re-attempt sending the buffered unroutable messages.
+const RETRY_UNROUTABLE_MESSAGES_INTERVAL: time::Duration = time::Duration::milliseconds(500);
+
 /// Number of times to attempt reconnection when trying to re-establish a connection.
 const MAX_RECONNECT_ATTEMPTS: usize = 6;
 
@@ -81,6 +85,10 @@ const PREFER_PREVIOUSLY_CONNECTED_PEER: f64 = 0.6;
 pub(crate) const UPDATE_CONNECTION_STORE_INTERVAL: time::Duration = time::Duration::minutes(1);
 /// How often to poll the NetworkState for closed connections we'd like to re-establish.
 pub(crate) const POLL_CONNECTION_STORE_INTERVAL: time::Duration = time::Duration::minutes(1);
+/// How often to drop expired AnnounceAccounts and to refresh our own ones.
+const REFRESH_ACCOUNTS_INTERVAL: time::Duration = time::Duration::minutes(1);
+/// How often to delete the orphaned graph components from DB.
+const COMPONENTS_COMPACTION_INTERVAL: time::Duration = time::Duration::hours(1);
 
 /// Actor that manages peers connections.
 pub struct PeerManagerActor {
@@ -166,6 +174,17 @@ impl actix::Actor for PeerManagerActor {
             }
         }));
 
+        // Periodically retry sending the messages which couldn't be routed.
+        let clock = self.clock.clone();
+        let state = self.state.clone();
+        ctx.spawn(wrap_future(async move {
+            let mut interval = time::Interval::new(clock.now(), RETRY_UNROUTABLE_MESSAGES_INTERVAL);
+            loop {
+                interval.tick(&clock).await;
+                state.retry_unroutable_messages(&clock);
+            }
+        }));
+
         // Periodically update the connection store.
         let clock = self.clock.clone();
         let state = self.state.clone();
@@ -177,6 +196,32 @@ impl actix::Actor for PeerManagerActor {
             }
         }));
 
+        // Periodically expire stale accounts and refresh our own.
+        let clock = self.clock.clone();
+        let state = self.state.clone();
+        ctx.spawn(wrap_future(async move {
+            let mut interval = time::Interval::new(clock.now(), REFRESH_ACCOUNTS_INTERVAL);
+            loop {
+                interval.tick(&clock).await;
+                state.refresh_accounts(&clock);
+            }
+        }));
+
+        // Periodically delete the orphaned graph components from DB.
+        // The first compaction is delayed, so that it doesn't slow down the node startup.
+        let clock = self.clock.clone();
+        let state = self.state.clone();
+        ctx.spawn(wrap_future(async move {
+            let mut interval = time::Interval::new(
+                clock.now() + COMPONENTS_COMPACTION_INTERVAL,
+                COMPONENTS_COMPACTION_INTERVAL,
+            );
+            loop {
+                interval.tick(&clock).await;
+                state.graph.compact_components().await;
+            }
+        }));
+
         // Periodically prints bandwidth stats for each peer.
         self.report_bandwidth_stats_trigger(ctx, REPORT_BANDWIDTH_STATS_TRIGGER_INTERVAL);
 
@@ -235,7 +280,7 @@ impl PeerManagerActor {
             client,
             shards_manager_adapter,
             whitelist_nodes,
-        ));
+        )?);
         arbiter.spawn({
             let arbiter = arbiter.clone();
             let state = state.clone();
@@ -282,11 +327,28 @@ impl PeerManagerActor {
                         async move {
                             loop {
                                 interval.tick(&clock).await;
+                                state.tier1_expire_accounts_data(&clock);
                                 state.tier1_request_full_sync();
                                 state.tier1_advertise_proxies(&clock).await;
                             }
                         }
                     });
+                    // Resolve the public address of this node via STUN periodically.
+                    if let Some(config::ValidatorProxies::Dynamic(_)) =
+                        state.config.validator.as_ref().map(|vc| &vc.proxies)
+                    {
+                        arbiter.spawn({
+                            let clock = clock.clone();
+                            let state = state.clone();
+                            let mut interval = time::Interval::new(clock.now(), cfg.stun_resolve_interval);
+                            async move {
+                                loop {
+                                    interval.tick(&clock).await;
+                                    state.tier1_resolve_public_addr(&clock).await;
+                                }
+                            }
+                        });
+                    }
                     // Update TIER1 connections periodically.
                     arbiter.spawn({
                         let clock = clock.clone();
@@ -300,6 +362,18 @@ impl PeerManagerActor {
                             }
                         }
                     });
+                    // Probe liveness of TIER1 connections periodically.
+                    arbiter.spawn({
+                        let clock = clock.clone();
+                        let state = state.clone();
+                        let mut interval = time::Interval::new(clock.now(), cfg.liveness_ping_interval);
+                        async move {
+                            loop {
+                                interval.tick(&clock).await;
+                                state.tier1_check_liveness(&clock);
+                            }
+                        }
+                    });
                 }
                 // Periodically poll the connection store for connections we'd like to re-establish
                 arbiter.spawn({
@@ -614,6 +688,11 @@ impl PeerManagerActor {
         // Find peers that are not reliable (too much behind) - and make sure that we're not routing messages through them.
         let unreliable_peers = self.unreliable_peers();
         metrics::PEER_UNRELIABLE.set(unreliable_peers.len() as i64);
+        for peer_id in &unreliable_peers {
+            if !self.state.graph.is_unreliable(peer_id) {
+                tracing::debug!(target: "network", %peer_id, "Peer became unreliable, not routing through it");
+            }
+        }
         self.state.graph.set_unreliable_peers(unreliable_peers);
 
         let new_interval = min(max_interval, interval * EXPONENTIAL_BACKOFF_RATIO);
@@ -668,7 +747,8 @@ impl PeerManagerActor {
     }
 
     pub(crate) fn get_network_info(&self) -> NetworkInfo {
-        let tier1 = self.state.tier1.load();
+        let tier1_outbound = self.state.tier1_outbound.load();
+        let tier1_inbound = self.state.tier1_inbound.load();
         let tier2 = self.state.tier2.load();
         let now = self.clock.now();
         let graph = self.state.graph.load();
@@ -687,7 +767,12 @@ impl PeerManagerActor {
         };
         NetworkInfo {
             connected_peers: tier2.ready.values().map(connected_peer).collect(),
-            tier1_connections: tier1.ready.values().map(connected_peer).collect(),
+            tier1_connections: tier1_outbound
+                .ready
+                .values()
+                .chain(tier1_inbound.ready.values())
+                .map(connected_peer)
+                .collect(),
             num_connected_peers: tier2.ready.len(),
             peer_max_count: self.state.config.max_num_peers,
             highest_height_peers: self.highest_height_peers(),
@@ -830,8 +915,9 @@ impl PeerManagerActor {
             }
             NetworkRequests::AnnounceAccount(announce_account) => {
                 let state = self.state.clone();
+                let clock = self.clock.clone();
                 ctx.spawn(wrap_future(async move {
-                    state.add_accounts(vec![announce_account]).await;
+                    state.add_accounts(&clock, vec![announce_account]).await;
                 }));
                 NetworkResponses::NoResponse
             }
@@ -1079,19 +1165,69 @@ impl actix::Handler<GetDebugStatus> for PeerManagerActor {
                 });
                 DebugStatus::PeerStore(PeerStoreView { peer_states: peer_states_view })
             }
-            GetDebugStatus::Graph => DebugStatus::Graph(NetworkGraphView {
-                edges: self
-                    .state
-                    .graph
-                    .load()
-                    .edges
-                    .values()
-                    .map(|edge| {
-                        let key = edge.key();
-                        EdgeView { peer0: key.0.clone(), peer1: key.1.clone(), nonce: edge.nonce() }
-                    })
-                    .collect(),
-            }),
+            GetDebugStatus::Graph => {
+                let graph = self.state.graph.load();
+                DebugStatus::Graph(NetworkGraphView {
+                    edges: graph
+                        .edges
+                        .values()
+                        .map(|edge| {
+                            let key = edge.key();
+                            EdgeView {
+                                peer0: key.0.clone(),
+                                peer1: key.1.clone(),
+                                nonce: edge.nonce(),
+                            }
+                        })
+                        .collect(),
+                    total_edges: graph.total_edges as u64,
+                    active_edges: graph.active_edges,
+                    reachable_peer_count: graph.reachable_peer_count as u64,
+                    local_edge_history: graph
+                        .local_edges
+                        .values()
+                        .filter_map(|edge| {
+                            let key = edge.key();
+                            let transitions = self.state.graph.edge_history(key);
+                            if transitions.is_empty() {
+                                return None;
+                            }
+                            Some(EdgeHistoryView {
+                                peer0: key.0.clone(),
+                                peer1: key.1.clone(),
+                                transitions: transitions
+                                    .into_iter()
+                                    .map(|t| EdgeTransitionView {
+                                        nonce: t.nonce,
+                                        state: format!("{:?}", t.state),
+                                        timestamp: t.at.unix_timestamp(),
+                                    })
+                                    .collect(),
+                            })
+                        })
+                        .collect(),
+                    recalc_latency: self.state.graph.recalc_latency_stats().map(|stats| {
+                        LatencyStatsView {
+                            samples: stats.samples as u64,
+                            p50_us: stats.p50.whole_microseconds() as u64,
+                            p99_us: stats.p99.whole_microseconds() as u64,
+                            max_us: stats.max.whole_microseconds() as u64,
+                        }
+                    }),
+                    peer_accounts: {
+                        let routing_table = &self.state.graph.routing_table;
+                        let owners: BTreeSet<_> =
+                            routing_table.snapshot().account_owners.values().cloned().collect();
+                        owners
+                            .into_iter()
+                            .map(|peer_id| PeerAccountsView {
+                                accounts: routing_table.accounts_for_peer(&peer_id),
+                                peer_id,
+                            })
+                            .collect()
+                    },
+                })
+            }
             GetDebugStatus::RecentOutboundConnections => {
                 DebugStatus::RecentOutboundConnections(RecentOutboundConnectionsView {
                     recent_outbound_connections: self
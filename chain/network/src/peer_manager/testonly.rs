@@ -446,12 +446,15 @@ impl ActorHandler {
         }
     }
 
-    pub async fn wait_for_account_owner(&self, account: &AccountId) -> PeerId {
+    pub async fn wait_for_account_owner(&self, clock: &time::Clock, account: &AccountId) -> PeerId {
         let mut events = self.events.from_now();
         loop {
+            let clock = clock.clone();
             let account = account.clone();
             let got = self
-                .with_state(|s| async move { s.graph.routing_table.account_owner(&account) })
+                .with_state(
+                    |s| async move { s.graph.routing_table.account_owner(&clock, &account) },
+                )
                 .await;
             if let Some(got) = got {
                 return got;
@@ -490,6 +493,14 @@ impl ActorHandler {
         .await;
     }
 
+    pub async fn tier1_check_liveness(&self, clock: &time::Clock) {
+        let clock = clock.clone();
+        self.with_state(move |s| async move {
+            s.tier1_check_liveness(&clock);
+        })
+        .await;
+    }
+
     /// Executes `NetworkState::update_connection_store` method.
     pub async fn update_connection_store(&self, clock: &time::Clock) {
         let clock = clock.clone();
@@ -1,24 +1,33 @@
 use crate::blacklist;
 use crate::broadcast;
+use crate::concurrency::rate;
 use crate::config::NetworkConfig;
 use crate::network_protocol::testonly as data;
-use crate::network_protocol::{Edge, Encoding, Ping, Pong, RoutedMessageBody, RoutingTableUpdate};
+use crate::network_protocol::{
+    Edge, Encoding, PeerIdOrHash, Ping, Pong, RawRoutedMessage, RoutedMessageBody,
+    RoutingTableUpdate,
+};
 use crate::peer;
 use crate::peer::peer_actor::{
     ClosingReason, ConnectionClosedEvent, DROP_DUPLICATED_MESSAGES_PERIOD,
 };
 use crate::peer_manager;
+use crate::peer_manager::network_state::routing::{AccountOwnerSource, RouteExplanation};
 use crate::peer_manager::peer_manager_actor::Event as PME;
 use crate::peer_manager::testonly::start as start_pm;
 use crate::peer_manager::testonly::Event;
 use crate::private_actix::RegisterPeerError;
 use crate::store;
 use crate::tcp;
+use crate::testonly::fake_client;
 use crate::testonly::{abort_on_panic, make_rng, Rng};
 use crate::types::PeerMessage;
 use crate::types::{PeerInfo, ReasonForBan};
 use near_async::time;
+use near_primitives::block::{Approval, ApprovalInner};
 use near_primitives::network::PeerId;
+use near_primitives::types::AccountId;
+use near_primitives::validator_signer::ValidatorSigner as _;
 use near_store::db::TestDB;
 use pretty_assertions::assert_eq;
 use rand::seq::IteratorRandom;
@@ -324,6 +333,52 @@ pub async fn wait_for_message_dropped(events: &mut broadcast::Receiver<Event>) {
         .await;
 }
 
+// test that a peer flooding us with routed messages doesn't starve other peers
+#[tokio::test]
+async fn peer_rate_limit() {
+    abort_on_panic();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+
+    tracing::info!(target:"test", "start three nodes");
+    let mut cfg = chain.make_config(rng);
+    cfg.peer_routed_message_rate_limit = rate::Limit { qps: 1., burst: 5 };
+    let pm0 = start_pm(clock.clock(), TestDB::new(), cfg, chain.clone()).await;
+    let pm1 = start_pm(clock.clock(), TestDB::new(), chain.make_config(rng), chain.clone()).await;
+    let pm2 = start_pm(clock.clock(), TestDB::new(), chain.make_config(rng), chain.clone()).await;
+
+    let id0 = pm0.cfg.node_id();
+    let id1 = pm1.cfg.node_id();
+    let id2 = pm2.cfg.node_id();
+
+    pm1.connect_to(&pm0.peer_info(), tcp::Tier::T2).await;
+    pm2.connect_to(&pm0.peer_info(), tcp::Tier::T2).await;
+    pm1.wait_for_routing_table(&[
+        (id0.clone(), vec![id0.clone()]),
+        (id2.clone(), vec![id0.clone()]),
+    ])
+    .await;
+    pm2.wait_for_routing_table(&[
+        (id0.clone(), vec![id0.clone()]),
+        (id1.clone(), vec![id0.clone()]),
+    ])
+    .await;
+
+    tracing::info!(target:"test", "flood {id0} with pings from {id1}");
+    let mut pm0_ev = pm0.events.from_now();
+    for nonce in 0..20 {
+        pm1.send_ping(&clock.clock(), nonce, id0.clone()).await;
+    }
+    wait_for_message_dropped(&mut pm0_ev).await;
+
+    tracing::info!(target:"test", "send ping from {id2} to {id0}");
+    let mut pm0_ev = pm0.events.from_now();
+    pm2.send_ping(&clock.clock(), 100, id0.clone()).await;
+    wait_for_ping(&mut pm0_ev, Ping { nonce: 100, source: id2.clone() }).await;
+}
+
 // test ping in a two-node network
 #[tokio::test]
 async fn ping_simple() {
@@ -358,6 +413,10 @@ async fn ping_simple() {
     tracing::info!(target:"test", "await pong at {id0}");
     wait_for_pong(&mut pm0_ev, Pong { nonce: 0, source: id1.clone() }).await;
 
+    tracing::info!(target:"test", "check that {id0} recorded the round trip time");
+    let rtt = pm0.with_state(move |s| async move { s.rtt.lock().get(&id1) }).await;
+    assert!(rtt.is_some());
+
     drop(pm0);
     drop(pm1);
 }
@@ -927,6 +986,171 @@ async fn ttl() {
     }
 }
 
+/// Test that copies of an important message are handled just once.
+#[tokio::test]
+async fn important_message_deduplicated() {
+    abort_on_panic();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+    let pm = peer_manager::testonly::start(
+        clock.clock(),
+        near_store::db::TestDB::new(),
+        chain.make_config(rng),
+        chain.clone(),
+    )
+    .await;
+    let cfg = peer::testonly::PeerConfig {
+        network: chain.make_config(rng),
+        chain,
+        force_encoding: Some(Encoding::Proto),
+    };
+    let stream = tcp::Stream::connect(&pm.peer_info(), tcp::Tier::T2).await.unwrap();
+    let mut peer = peer::testonly::PeerHandle::start_endpoint(clock.clock(), cfg, stream).await;
+    peer.complete_handshake().await;
+    pm.wait_for_routing_table(&[(peer.cfg.id(), vec![peer.cfg.id()])]).await;
+
+    let signer = data::make_validator_signer(rng);
+    let make_approval = |rng: &mut Rng| {
+        let inner = ApprovalInner::Endorsement(data::make_hash(rng));
+        let target_height = rng.gen_range(0..100000);
+        Approval {
+            signature: signer.sign_approval(&inner, target_height),
+            account_id: signer.validator_id().clone(),
+            target_height,
+            inner,
+        }
+    };
+    let mut events = pm.events.from_now();
+    let want = make_approval(rng);
+    let msg = RoutedMessageBody::BlockApproval(want.clone());
+    let msg = Box::new(peer.routed_message(msg, pm.cfg.node_id(), 2, Some(clock.now_utc())));
+    tracing::info!(target:"test", "send the same important message 3 times");
+    for _ in 0..3 {
+        peer.send(PeerMessage::Routed(msg.clone())).await;
+        // Make sure that the copies are not dropped by the per-connection dedup.
+        clock.advance(DROP_DUPLICATED_MESSAGES_PERIOD + time::Duration::milliseconds(1));
+    }
+    tracing::info!(target:"test", "send a distinct important message");
+    let last = make_approval(rng);
+    let msg = RoutedMessageBody::BlockApproval(last.clone());
+    let msg = Box::new(peer.routed_message(msg, pm.cfg.node_id(), 2, Some(clock.now_utc())));
+    peer.send(PeerMessage::Routed(msg)).await;
+
+    tracing::info!(target:"test", "wait for the distinct message to be handled");
+    let mut handled = 0;
+    let mut dropped = 0;
+    let mut last_handled = false;
+    while !(last_handled && handled > 0) {
+        match events.recv().await {
+            Event::Client(fake_client::Event::BlockApproval(got, _)) if got == want => handled += 1,
+            Event::Client(fake_client::Event::BlockApproval(got, _)) if got == last => {
+                last_handled = true
+            }
+            Event::PeerManager(PME::RoutedMessageDropped) => dropped += 1,
+            _ => {}
+        }
+    }
+    assert_eq!(1, handled);
+    assert_eq!(2, dropped);
+}
+
+/// Test that the routed messages are signed with the TTL configured for their type.
+#[tokio::test]
+async fn routed_message_ttl_override() {
+    abort_on_panic();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+    let mut cfg = chain.make_config(rng);
+    cfg.routed_message_ttl_overrides.insert("Ping".to_string(), 2);
+    let default_ttl = cfg.routed_message_ttl;
+    assert_ne!(2, default_ttl);
+    let pm = peer_manager::testonly::start(clock.clock(), TestDB::new(), cfg, chain.clone()).await;
+
+    let target = PeerIdOrHash::PeerId(data::make_peer_id(rng));
+    let source = pm.cfg.node_id();
+    let ping = RoutedMessageBody::Ping(Ping { nonce: 0, source: source.clone() });
+    let pong = RoutedMessageBody::Pong(Pong { nonce: 0, source });
+    let c = clock.clock();
+    let got = pm
+        .with_state(move |s| async move {
+            [ping, pong].map(|body| {
+                s.sign_message(&c, RawRoutedMessage { target: target.clone(), body }).ttl
+            })
+        })
+        .await;
+    assert_eq!([2, default_ttl], got);
+}
+
+/// Test that the ForwardTx messages above max_forward_txs_per_block are dropped,
+/// until the next block arrives.
+#[tokio::test]
+async fn max_forward_txs_per_block() {
+    abort_on_panic();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+    let mut cfg = chain.make_config(rng);
+    cfg.max_forward_txs_per_block = Some(2);
+    let pm = peer_manager::testonly::start(clock.clock(), TestDB::new(), cfg, chain.clone()).await;
+    let cfg = peer::testonly::PeerConfig {
+        network: chain.make_config(rng),
+        chain: chain.clone(),
+        force_encoding: Some(Encoding::Proto),
+    };
+    let stream = tcp::Stream::connect(&pm.peer_info(), tcp::Tier::T2).await.unwrap();
+    let mut peer = peer::testonly::PeerHandle::start_endpoint(clock.clock(), cfg, stream).await;
+    peer.complete_handshake().await;
+    pm.wait_for_routing_table(&[(peer.cfg.id(), vec![peer.cfg.id()])]).await;
+
+    let mut events = pm.events.from_now();
+    tracing::info!(target:"test", "send 3 transactions, the last one exceeding the limit");
+    let txs: Vec<_> = (0..3).map(|_| data::make_signed_transaction(rng)).collect();
+    for tx in &txs {
+        let msg = RoutedMessageBody::ForwardTx(tx.clone());
+        let msg = Box::new(peer.routed_message(msg, pm.cfg.node_id(), 2, Some(clock.now_utc())));
+        peer.send(PeerMessage::Routed(msg)).await;
+    }
+    let mut got = vec![];
+    let mut dropped = 0;
+    while got.len() + dropped < txs.len() {
+        match events.recv().await {
+            Event::Client(fake_client::Event::Transaction(tx)) => got.push(tx),
+            Event::PeerManager(PME::RoutedMessageDropped) => dropped += 1,
+            _ => {}
+        }
+    }
+    assert_eq!(&txs[..2], &got[..]);
+    assert_eq!(1, dropped);
+
+    tracing::info!(target:"test", "send a block, which resets the limit");
+    peer.send(PeerMessage::Block(chain.blocks[5].clone())).await;
+    events
+        .recv_until(|ev| match ev {
+            Event::Client(fake_client::Event::Block(_)) => Some(()),
+            _ => None,
+        })
+        .await;
+
+    tracing::info!(target:"test", "a transaction is accepted again");
+    let tx = data::make_signed_transaction(rng);
+    let msg = RoutedMessageBody::ForwardTx(tx.clone());
+    let msg = Box::new(peer.routed_message(msg, pm.cfg.node_id(), 2, Some(clock.now_utc())));
+    peer.send(PeerMessage::Routed(msg)).await;
+    let got = events
+        .recv_until(|ev| match ev {
+            Event::Client(fake_client::Event::Transaction(tx)) => Some(tx),
+            Event::PeerManager(PME::RoutedMessageDropped) => panic!("transaction dropped"),
+            _ => None,
+        })
+        .await;
+    assert_eq!(tx, got);
+}
+
 /// After the initial exchange, all subsequent SyncRoutingTable messages are
 /// expected to contain only the diff of the known data.
 #[tokio::test]
@@ -1220,7 +1444,7 @@ async fn do_not_block_announce_account_broadcast() {
     let pm1 = start_pm(clock.clock(), db1.clone(), chain.make_config(rng), chain.clone()).await;
     pm1.connect_to(&pm0.peer_info(), tcp::Tier::T2).await;
     pm1.announce_account(aa.clone()).await;
-    assert_eq!(&aa.peer_id, &pm0.wait_for_account_owner(&aa.account_id).await);
+    assert_eq!(&aa.peer_id, &pm0.wait_for_account_owner(&clock.clock(), &aa.account_id).await);
     drop(pm0);
     drop(pm1);
 
@@ -1233,7 +1457,7 @@ async fn do_not_block_announce_account_broadcast() {
     pm1.connect_to(&pm0.peer_info(), tcp::Tier::T2).await;
     pm2.connect_to(&pm0.peer_info(), tcp::Tier::T2).await;
     pm1.announce_account(aa.clone()).await;
-    assert_eq!(&aa.peer_id, &pm2.wait_for_account_owner(&aa.account_id).await);
+    assert_eq!(&aa.peer_id, &pm2.wait_for_account_owner(&clock.clock(), &aa.account_id).await);
 }
 
 /// Check that two archival nodes keep connected after network rebalance. Nodes 0 and 1 are archival nodes, others aren't.
@@ -1318,6 +1542,35 @@ async fn archival_node() {
     drop(pm4);
 }
 
+#[tokio::test]
+async fn explain_route() {
+    abort_on_panic();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+
+    let pm = start_pm(clock.clock(), TestDB::new(), chain.make_config(rng), chain.clone()).await;
+    let explain_route =
+        |account_id: AccountId| pm.with_state(move |s| async move { s.explain_route(&account_id) });
+
+    tracing::info!(target:"test", "Unknown account.");
+    let aa = data::make_announce_account(rng);
+    let want = RouteExplanation { owner: None, next_hops: vec![], tier1: None };
+    assert_eq!(want, explain_route(aa.account_id.clone()).await);
+
+    tracing::info!(target:"test", "Known account, owned by an unreachable peer.");
+    let c = clock.clock();
+    let accounts = vec![aa.clone()];
+    pm.with_state(move |s| async move { s.add_accounts(&c, accounts).await }).await;
+    let want = RouteExplanation {
+        owner: Some((aa.peer_id.clone(), AccountOwnerSource::AnnounceAccount)),
+        next_hops: vec![],
+        tier1: None,
+    };
+    assert_eq!(want, explain_route(aa.account_id.clone()).await);
+}
+
 /// Awaits for ConnectionClosed event for a given `stream_id`.
 async fn wait_for_stream_closed(
     events: &mut broadcast::Receiver<Event>,
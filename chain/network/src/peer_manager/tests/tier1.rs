@@ -1,18 +1,32 @@
 use crate::config;
 use crate::network_protocol::testonly as data;
-use crate::network_protocol::{PeerAddr, PeerMessage, RoutedMessageBody};
+use crate::network_protocol::{
+    AccountData, Encoding, Handshake, OwnedAccount, PartialEdgeInfo, PeerAddr, PeerIdOrHash,
+    PeerMessage, Pong, RawRoutedMessage, RoutedMessageBody, VersionedAccountData,
+};
+use crate::peer::peer_actor::ClosingReason;
 use crate::peer_manager;
+use crate::peer_manager::connection;
 use crate::peer_manager::peer_manager_actor::Event as PME;
 use crate::peer_manager::testonly::start as start_pm;
 use crate::peer_manager::testonly::Event;
+use crate::private_actix::RegisterPeerError;
 use crate::stun;
 use crate::tcp;
+use crate::testonly::stream::Stream;
 use crate::testonly::{make_rng, Rng};
+use crate::types::PeerManagerMessageRequest;
 use near_async::time;
 use near_o11y::testonly::init_test_logger;
+use near_o11y::WithSpanContextExt;
 use near_primitives::block_header::{Approval, ApprovalInner};
+use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
 use near_primitives::validator_signer::ValidatorSigner;
+use near_primitives::version::PROTOCOL_VERSION;
 use near_store::db::TestDB;
+use rand::seq::IteratorRandom as _;
+use rand::seq::SliceRandom as _;
 use rand::Rng as _;
 use std::collections::HashSet;
 use std::sync::Arc;
@@ -140,6 +154,273 @@ async fn first_proxy_advertisement() {
     );
 }
 
+// Unchanged list of proxies is not broadcasted again until the refresh interval passes.
+#[tokio::test]
+async fn unchanged_proxies_advertisement_is_skipped() {
+    init_test_logger();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+    let refresh_interval = time::Duration::hours(1);
+    let mut cfg = chain.make_config(rng);
+    cfg.tier1.as_mut().unwrap().advertise_proxies_refresh_interval = refresh_interval;
+    let pm = start_pm(clock.clock(), TestDB::new(), cfg, chain.clone()).await;
+    let chain_info = peer_manager::testonly::make_chain_info(&chain, &[&pm.cfg]);
+    pm.set_chain_info(chain_info).await;
+
+    let first = pm.tier1_advertise_proxies(&clock.clock()).await.unwrap();
+    tracing::info!(target:"test", "Nothing has changed, so the broadcast is skipped.");
+    clock.advance(refresh_interval - time::Duration::seconds(1));
+    let got = pm.tier1_advertise_proxies(&clock.clock()).await.unwrap();
+    assert_eq!(first, got);
+
+    tracing::info!(target:"test", "Once the refresh interval passes, a new version is broadcasted.");
+    clock.advance(time::Duration::seconds(1));
+    let got = pm.tier1_advertise_proxies(&clock.clock()).await.unwrap();
+    assert!(got.version > first.version);
+    assert_eq!(first.proxies, got.proxies);
+}
+
+async fn my_tier1_proxies(pm: &peer_manager::testonly::ActorHandler) -> Vec<PeerAddr> {
+    pm.with_state(|s| async move { s.my_tier1_proxies(s.config.validator.as_ref().unwrap()) }).await
+}
+
+#[tokio::test]
+async fn my_tier1_proxies_static() {
+    init_test_logger();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+
+    let proxy = start_pm(clock.clock(), TestDB::new(), chain.make_config(rng), chain.clone()).await;
+    let proxy_addr =
+        PeerAddr { peer_id: proxy.cfg.node_id(), addr: **proxy.cfg.node_addr.as_ref().unwrap() };
+    let mut cfg = chain.make_config(rng);
+    cfg.validator.as_mut().unwrap().proxies =
+        config::ValidatorProxies::Static(vec![proxy_addr.clone()]);
+    let pm = start_pm(clock.clock(), TestDB::new(), cfg, chain.clone()).await;
+
+    tracing::info!(target:"test", "Not connected to the proxy yet.");
+    assert_eq!(Vec::<PeerAddr>::new(), my_tier1_proxies(&pm).await);
+
+    tracing::info!(target:"test", "Connect to the proxy.");
+    let chain_info = peer_manager::testonly::make_chain_info(&chain, &[&pm.cfg]);
+    for pm in [&pm, &proxy] {
+        pm.set_chain_info(chain_info.clone()).await;
+    }
+    pm.tier1_advertise_proxies(&clock.clock()).await;
+    assert_eq!(vec![proxy_addr], my_tier1_proxies(&pm).await);
+}
+
+#[tokio::test]
+async fn my_tier1_proxies_dynamic() {
+    init_test_logger();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+
+    let stun_server = stun::testonly::Server::new().await;
+    let mut cfg = chain.make_config(rng);
+    cfg.validator.as_mut().unwrap().proxies =
+        config::ValidatorProxies::Dynamic(vec![stun_server.addr()]);
+    let pm = start_pm(clock.clock(), TestDB::new(), cfg, chain.clone()).await;
+
+    tracing::info!(target:"test", "No loop connection yet.");
+    assert_eq!(Vec::<PeerAddr>::new(), my_tier1_proxies(&pm).await);
+
+    tracing::info!(target:"test", "Establish a loop connection.");
+    let chain_info = peer_manager::testonly::make_chain_info(&chain, &[&pm.cfg]);
+    pm.set_chain_info(chain_info).await;
+    pm.tier1_advertise_proxies(&clock.clock()).await;
+    let want =
+        vec![PeerAddr { peer_id: pm.cfg.node_id(), addr: **pm.cfg.node_addr.as_ref().unwrap() }];
+    assert_eq!(want, my_tier1_proxies(&pm).await);
+
+    drop(pm);
+    stun_server.close().await;
+}
+
+async fn missing_proxies(
+    pm: &peer_manager::testonly::ActorHandler,
+    proxies: &[PeerAddr],
+) -> Vec<PeerAddr> {
+    let proxies = proxies.to_vec();
+    pm.with_state(move |s| async move { s.tier1_missing_proxies(&proxies) }).await
+}
+
+#[tokio::test]
+async fn tier1_missing_proxies() {
+    init_test_logger();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+
+    // p0 is available, while p1 is not.
+    let p0cfg = chain.make_config(rng);
+    let p1cfg = chain.make_config(rng);
+    let proxies = vec![
+        PeerAddr { peer_id: p0cfg.node_id(), addr: **p0cfg.node_addr.as_ref().unwrap() },
+        PeerAddr { peer_id: p1cfg.node_id(), addr: **p1cfg.node_addr.as_ref().unwrap() },
+    ];
+    let mut cfg = chain.make_config(rng);
+    cfg.validator.as_mut().unwrap().proxies = config::ValidatorProxies::Static(proxies.clone());
+    let p0 = start_pm(clock.clock(), TestDB::new(), p0cfg, chain.clone()).await;
+    let pm = start_pm(clock.clock(), TestDB::new(), cfg, chain.clone()).await;
+
+    tracing::info!(target:"test", "All proxies are missing initially.");
+    assert_eq!(proxies.clone(), missing_proxies(&pm, &proxies).await);
+
+    tracing::info!(target:"test", "Connect to the available proxy.");
+    let chain_info = peer_manager::testonly::make_chain_info(&chain, &[&pm.cfg]);
+    for pm in [&pm, &p0] {
+        pm.set_chain_info(chain_info.clone()).await;
+    }
+    pm.tier1_advertise_proxies(&clock.clock()).await;
+    assert_eq!(vec![proxies[1].clone()], missing_proxies(&pm, &proxies).await);
+
+    tracing::info!(target:"test", "A proxy with an outbound handshake in progress is not missing.");
+    let p1_id = proxies[1].peer_id.clone();
+    let permit = pm
+        .with_state(move |s| async move { s.tier1_outbound.start_outbound(p1_id).unwrap() })
+        .await;
+    assert_eq!(Vec::<PeerAddr>::new(), missing_proxies(&pm, &proxies).await);
+
+    tracing::info!(target:"test", "Once the handshake is abandoned, the proxy is missing again.");
+    drop(permit);
+    assert_eq!(vec![proxies[1].clone()], missing_proxies(&pm, &proxies).await);
+}
+
+// A TIER1 node should be able to connect to its proxy, even if the proxy
+// already has an inbound connection to the node.
+#[tokio::test]
+async fn inbound_and_outbound_connection_to_proxy() {
+    init_test_logger();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+
+    let proxy = start_pm(clock.clock(), TestDB::new(), chain.make_config(rng), chain.clone()).await;
+    let proxy_id = proxy.cfg.node_id();
+    let proxy_addr =
+        PeerAddr { peer_id: proxy_id.clone(), addr: **proxy.cfg.node_addr.as_ref().unwrap() };
+    let mut cfg = chain.make_config(rng);
+    cfg.validator.as_mut().unwrap().proxies =
+        config::ValidatorProxies::Static(vec![proxy_addr.clone()]);
+    let pm = start_pm(clock.clock(), TestDB::new(), cfg, chain.clone()).await;
+    let pm_id = pm.cfg.node_id();
+
+    tracing::info!(target:"test", "Both nodes are TIER1 validators.");
+    let chain_info = peer_manager::testonly::make_chain_info(&chain, &[&pm.cfg, &proxy.cfg]);
+    for pm in [&pm, &proxy] {
+        pm.set_chain_info(chain_info.clone()).await;
+    }
+
+    tracing::info!(target:"test", "The proxy connects to the node.");
+    tier1_connect(&proxy, &pm).await.unwrap();
+    assert_eq!(vec![proxy_addr.clone()], missing_proxies(&pm, &[proxy_addr.clone()]).await);
+
+    tracing::info!(target:"test", "The node connects to the proxy.");
+    pm.tier1_advertise_proxies(&clock.clock()).await;
+    assert_eq!(vec![proxy_addr.clone()], my_tier1_proxies(&pm).await);
+    assert_eq!((true, true), tier1_connection_to(&pm, &proxy_id).await);
+    assert_eq!((true, true), tier1_connection_to(&proxy, &pm_id).await);
+}
+
+// Returns whether `pm` has an (inbound, outbound) TIER1 connection to `peer_id`.
+async fn tier1_connection_to(
+    pm: &peer_manager::testonly::ActorHandler,
+    peer_id: &PeerId,
+) -> (bool, bool) {
+    let peer_id = peer_id.clone();
+    pm.with_state(move |s| async move {
+        (
+            s.tier1_inbound.load().ready.contains_key(&peer_id),
+            s.tier1_outbound.load().ready.contains_key(&peer_id),
+        )
+    })
+    .await
+}
+
+// Makes `from` connect to `to` over TIER1.
+// Returns the reason for which `to` has rejected the connection, if any.
+async fn tier1_connect(
+    from: &peer_manager::testonly::ActorHandler,
+    to: &peer_manager::testonly::ActorHandler,
+) -> Result<(), ClosingReason> {
+    let mut events = to.events.from_now();
+    let stream = tcp::Stream::connect(&to.peer_info(), tcp::Tier::T1).await.unwrap();
+    let stream_id = stream.id();
+    from.actix
+        .addr
+        .do_send(PeerManagerMessageRequest::OutboundTcpConnect(stream).with_span_context());
+    events
+        .recv_until(|ev| match ev {
+            Event::PeerManager(PME::HandshakeCompleted(ev)) if ev.stream_id == stream_id => {
+                Some(Ok(()))
+            }
+            Event::PeerManager(PME::ConnectionClosed(ev)) if ev.stream_id == stream_id => {
+                Some(Err(ev.reason))
+            }
+            _ => None,
+        })
+        .await
+}
+
+// When 2 TIER1 nodes connect to each other at the same time, only the connection
+// from the node with the smaller PeerId is accepted. Once the tie is broken, the other
+// node can still connect in the opposite direction.
+#[tokio::test]
+async fn simultaneous_connect() {
+    init_test_logger();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+
+    let pm0 = start_pm(clock.clock(), TestDB::new(), chain.make_config(rng), chain.clone()).await;
+    let pm1 = start_pm(clock.clock(), TestDB::new(), chain.make_config(rng), chain.clone()).await;
+    let chain_info = peer_manager::testonly::make_chain_info(&chain, &[&pm0.cfg, &pm1.cfg]);
+    for pm in [&pm0, &pm1] {
+        pm.set_chain_info(chain_info.clone()).await;
+    }
+    let (small, big) =
+        if pm0.cfg.node_id() < pm1.cfg.node_id() { (&pm0, &pm1) } else { (&pm1, &pm0) };
+    let small_id = small.cfg.node_id();
+    let big_id = big.cfg.node_id();
+
+    tracing::info!(target:"test", "small rejects the connection from big, while connecting to big itself.");
+    let id = big_id.clone();
+    let permit =
+        small.with_state(move |s| async move { s.tier1_start_outbound(id).unwrap() }).await;
+    let got = tier1_connect(big, small).await;
+    assert_eq!(
+        Err(ClosingReason::RejectedByPeerManager(RegisterPeerError::PoolError(
+            connection::PoolError::AlreadyStartedConnecting
+        ))),
+        got
+    );
+    drop(permit);
+
+    tracing::info!(target:"test", "big accepts the connection from small, while connecting to small itself.");
+    let id = small_id.clone();
+    let permit = big.with_state(move |s| async move { s.tier1_start_outbound(id).unwrap() }).await;
+    tier1_connect(small, big).await.unwrap();
+    drop(permit);
+
+    assert_eq!((false, true), tier1_connection_to(small, &big_id).await);
+    assert_eq!((true, false), tier1_connection_to(big, &small_id).await);
+
+    tracing::info!(target:"test", "big connects to small in the other direction.");
+    tier1_connect(big, small).await.unwrap();
+    assert_eq!((true, true), tier1_connection_to(small, &big_id).await);
+    assert_eq!((true, true), tier1_connection_to(big, &small_id).await);
+}
+
 #[tokio::test]
 async fn direct_connections() {
     init_test_logger();
@@ -357,6 +638,190 @@ async fn proxy_change() {
     drop(p0);
 }
 
+/// Returns the TIER1 connection that `pm` would use to send a message to `target`.
+async fn get_tier1_proxy(
+    pm: &peer_manager::testonly::ActorHandler,
+    target: &peer_manager::testonly::ActorHandler,
+) -> Option<PeerId> {
+    let key = target.cfg.validator.as_ref().unwrap().signer.public_key();
+    pm.with_state(move |s| async move {
+        let accounts_data = s.accounts_data.load();
+        let conn = s.get_tier1_proxy(accounts_data.data.get(&key)?)?;
+        Some(conn.peer_info.id.clone())
+    })
+    .await
+}
+
+// Only TIER1 validators are allowed to send messages via proxies.
+// Here v1 stops being a TIER1 validator, while still having a connection to the proxy of v0.
+#[tokio::test]
+async fn proxy_only_for_tier1_validators() {
+    init_test_logger();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+
+    // v0 has proxy p0, v1 has proxy p1.
+    let p0cfg = chain.make_config(rng);
+    let p1cfg = chain.make_config(rng);
+    let mut v0cfg = chain.make_config(rng);
+    v0cfg.validator.as_mut().unwrap().proxies = config::ValidatorProxies::Static(vec![PeerAddr {
+        peer_id: p0cfg.node_id(),
+        addr: **p0cfg.node_addr.as_ref().unwrap(),
+    }]);
+    let mut v1cfg = chain.make_config(rng);
+    v1cfg.validator.as_mut().unwrap().proxies = config::ValidatorProxies::Static(vec![PeerAddr {
+        peer_id: p1cfg.node_id(),
+        addr: **p1cfg.node_addr.as_ref().unwrap(),
+    }]);
+
+    tracing::info!(target:"test", "Start all nodes.");
+    let p0 = start_pm(clock.clock(), TestDB::new(), p0cfg.clone(), chain.clone()).await;
+    let p1 = start_pm(clock.clock(), TestDB::new(), p1cfg.clone(), chain.clone()).await;
+    let v0 = start_pm(clock.clock(), TestDB::new(), v0cfg.clone(), chain.clone()).await;
+    let v1 = start_pm(clock.clock(), TestDB::new(), v1cfg.clone(), chain.clone()).await;
+    let hub = start_pm(clock.clock(), TestDB::new(), chain.make_config(rng), chain.clone()).await;
+    for pm in [&p0, &p1, &v0, &v1] {
+        hub.connect_to(&pm.peer_info(), tcp::Tier::T2).await;
+    }
+
+    tracing::info!(target:"test", "[v0,v1] are TIER1 nodes: v1 uses p0 to reach v0.");
+    let chain_info = peer_manager::testonly::make_chain_info(&chain, &[&v0.cfg, &v1.cfg]);
+    for pm in [&p0, &p1, &v0, &v1, &hub] {
+        pm.set_chain_info(chain_info.clone()).await;
+    }
+    establish_connections(&clock.clock(), &[&p0, &p1, &v0, &v1, &hub]).await;
+    assert_eq!(Some(p0cfg.node_id()), get_tier1_proxy(&v1, &v0).await);
+
+    tracing::info!(target:"test", "[v0] is the only TIER1 node: v1 cannot use the proxy any more.");
+    let chain_info = peer_manager::testonly::make_chain_info(&chain, &[&v0.cfg]);
+    for pm in [&p0, &p1, &v0, &v1, &hub] {
+        pm.set_chain_info(chain_info.clone()).await;
+    }
+    assert_eq!(None, get_tier1_proxy(&v1, &v0).await);
+}
+
+async fn connect_backoff(
+    pm: &peer_manager::testonly::ActorHandler,
+    peer_id: &PeerId,
+) -> Option<(u32, time::Instant)> {
+    let peer_id = peer_id.clone();
+    pm.with_state(move |s| async move {
+        s.tier1_connect_backoff.lock().get(&peer_id).map(|b| (b.failures, b.next_retry_at))
+    })
+    .await
+}
+
+// Connection attempts to an unreachable proxy are retried with exponential backoff.
+#[tokio::test]
+async fn proxy_connect_backoff() {
+    init_test_logger();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+
+    let min_delay = time::Duration::seconds(1);
+    let max_delay = time::Duration::seconds(4);
+    let mut cfg = chain.make_config(rng);
+    let tier1 = cfg.tier1.as_mut().unwrap();
+    tier1.connect_backoff_min = min_delay;
+    tier1.connect_backoff_max = max_delay;
+    let pm = start_pm(clock.clock(), TestDB::new(), cfg, chain.clone()).await;
+
+    tracing::info!(target:"test", "v1 is a TIER1 node advertising an unreachable proxy.");
+    let v1cfg = chain.make_config(rng);
+    // The address of the proxy is reserved, but nobody listens on it.
+    let proxy_cfg = chain.make_config(rng);
+    let proxy =
+        PeerAddr { peer_id: proxy_cfg.node_id(), addr: **proxy_cfg.node_addr.as_ref().unwrap() };
+    let chain_info = peer_manager::testonly::make_chain_info(&chain, &[&pm.cfg, &v1cfg]);
+    pm.set_chain_info(chain_info).await;
+    let signer = v1cfg.validator.as_ref().unwrap().signer.clone();
+    let data = VersionedAccountData {
+        data: AccountData { peer_id: v1cfg.node_id(), proxies: vec![proxy.clone()] },
+        account_key: signer.public_key(),
+        version: 1,
+        timestamp: clock.now_utc(),
+    };
+    let data = Arc::new(data.sign(signer.as_ref()).unwrap());
+    let c = clock.clock();
+    let res =
+        pm.with_state(move |s| async move { s.add_accounts_data(&c, vec![data]).await }).await;
+    assert!(res.is_none());
+
+    let mut want_delay = min_delay;
+    for failures in 1..=4 {
+        tracing::info!(target:"test", "Failed attempt {failures}.");
+        pm.tier1_connect(&clock.clock()).await;
+        let want = (failures, clock.now() + want_delay);
+        assert_eq!(Some(want), connect_backoff(&pm, &proxy.peer_id).await);
+
+        tracing::info!(target:"test", "No attempt is made before the delay passes.");
+        clock.advance(want_delay - time::Duration::milliseconds(1));
+        pm.tier1_connect(&clock.clock()).await;
+        assert_eq!(Some(want), connect_backoff(&pm, &proxy.peer_id).await);
+        clock.advance(time::Duration::milliseconds(1));
+        want_delay = std::cmp::min(want_delay * 2, max_delay);
+    }
+}
+
+// With a seeded RNG, the proxy to connect to is selected deterministically.
+#[tokio::test]
+async fn proxy_selection_with_seeded_rng() {
+    init_test_logger();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+    let pm = start_pm(clock.clock(), TestDB::new(), chain.make_config(rng), chain.clone()).await;
+
+    tracing::info!(target:"test", "v1 is a TIER1 node advertising a few unreachable proxies.");
+    let v1cfg = chain.make_config(rng);
+    // The addresses of the proxies are reserved, but nobody listens on them.
+    let proxies: Vec<_> = (0..5)
+        .map(|_| {
+            let cfg = chain.make_config(rng);
+            PeerAddr { peer_id: cfg.node_id(), addr: **cfg.node_addr.as_ref().unwrap() }
+        })
+        .collect();
+    let chain_info = peer_manager::testonly::make_chain_info(&chain, &[&pm.cfg, &v1cfg]);
+    pm.set_chain_info(chain_info).await;
+    // Make sure that the AccountData of pm is already there, so that the set of
+    // accounts doesn't change under tier1_connect().
+    pm.tier1_advertise_proxies(&clock.clock()).await;
+    let signer = v1cfg.validator.as_ref().unwrap().signer.clone();
+    let data = VersionedAccountData {
+        data: AccountData { peer_id: v1cfg.node_id(), proxies: proxies.clone() },
+        account_key: signer.public_key(),
+        version: 1,
+        timestamp: clock.now_utc(),
+    };
+    let data = Arc::new(data.sign(signer.as_ref()).unwrap());
+    let c = clock.clock();
+    let res =
+        pm.with_state(move |s| async move { s.add_accounts_data(&c, vec![data]).await }).await;
+    assert!(res.is_none());
+
+    tracing::info!(target:"test", "Connect with a seeded RNG.");
+    let seed = 2384912;
+    pm.with_state(move |s| async move {
+        *s.tier1_rng.lock() = Some(Box::new(make_rng(seed)));
+    })
+    .await;
+    pm.tier1_connect(&clock.clock()).await;
+
+    // Replay the RNG: accounts of pm and v1 are shuffled, then a proxy of v1 is chosen.
+    let mut want_rng = make_rng(seed);
+    [0, 1].shuffle(&mut want_rng);
+    let want = proxies.iter().choose(&mut want_rng).unwrap();
+    for p in &proxies {
+        let got = connect_backoff(&pm, &p.peer_id).await;
+        assert_eq!(p == want, got.is_some(), "proxy {}", p.peer_id);
+    }
+}
+
 #[tokio::test]
 async fn tier2_routing_using_accounts_data() {
     init_test_logger();
@@ -417,3 +882,196 @@ async fn stun_self_discovery() {
     stun_server1.close().await;
     stun_server2.close().await;
 }
+
+#[tokio::test]
+async fn stun_resolved_addr_is_advertised() {
+    init_test_logger();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+
+    let stun_server = stun::testonly::Server::new().await;
+    let mut cfg = chain.make_config(rng);
+    let vc = cfg.validator.as_mut().unwrap();
+    vc.proxies = config::ValidatorProxies::Dynamic(vec![stun_server.addr()]);
+    let pm = start_pm(clock.clock(), TestDB::new(), cfg, chain.clone()).await;
+    let want = PeerAddr { peer_id: pm.cfg.node_id(), addr: *pm.cfg.node_addr.unwrap() };
+
+    tracing::info!(target:"test", "resolve the public address via STUN");
+    let got = {
+        let clock = clock.clock();
+        pm.with_state(move |s| async move { s.tier1_resolve_public_addr(&clock).await }).await
+    };
+    assert_eq!(Some(want.clone()), got);
+
+    tracing::info!(target:"test", "close the stun server and advertize AccountData");
+    // The resolved address should be used, without querying the STUN server again.
+    stun_server.close().await;
+    let chain_info = peer_manager::testonly::make_chain_info(&chain, &[&pm.cfg]);
+    pm.set_chain_info(chain_info).await;
+    let got = pm.tier1_advertise_proxies(&clock.clock()).await.unwrap();
+    assert_eq!(vec![want], got.proxies);
+}
+
+#[tokio::test]
+async fn unroutable_message_is_retried() {
+    init_test_logger();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+
+    tracing::info!(target:"test", "start 2 nodes and connect them");
+    let pm0 = start_pm(clock.clock(), TestDB::new(), chain.make_config(rng), chain.clone()).await;
+    let pm1 = start_pm(clock.clock(), TestDB::new(), chain.make_config(rng), chain.clone()).await;
+    pm0.connect_to(&pm1.peer_info(), tcp::Tier::T2).await;
+
+    tracing::info!(target:"test", "send a message pm0 -> pm1 before the route is known");
+    let from_signer = pm0.cfg.validator.as_ref().unwrap().signer.clone();
+    let target = pm1.cfg.validator.as_ref().unwrap().signer.validator_id().clone();
+    let want = RoutedMessageBody::BlockApproval(make_block_approval(rng, from_signer.as_ref()));
+    let buffered = {
+        let clock = clock.clock();
+        let want = want.clone();
+        pm0.with_state(move |s| async move {
+            assert!(!s.send_message_to_account(&clock, &target, want));
+            s.unroutable_messages.lock().len()
+        })
+        .await
+    };
+    assert_eq!(1, buffered);
+
+    tracing::info!(target:"test", "propagate AccountsData");
+    let chain_info = peer_manager::testonly::make_chain_info(&chain, &[&pm1.cfg]);
+    for pm in [&pm0, &pm1] {
+        pm.set_chain_info(chain_info.clone()).await;
+    }
+    let data: HashSet<_> = pm1.tier1_advertise_proxies(&clock.clock()).await.into_iter().collect();
+    pm0.wait_for_accounts_data(&data).await;
+
+    tracing::info!(target:"test", "retry the buffered message and wait for pm1 to receive it");
+    let mut events = pm1.events.from_now();
+    {
+        let clock = clock.clock();
+        pm0.with_state(move |s| async move { s.retry_unroutable_messages(&clock) }).await;
+    }
+    let got = events
+        .recv_until(|ev| match ev {
+            Event::PeerManager(PME::MessageProcessed(_, PeerMessage::Routed(got))) => Some(got),
+            _ => None,
+        })
+        .await;
+    assert_eq!(pm0.cfg.node_id(), got.author);
+    assert_eq!(want, got.body);
+    let buffered = pm0.with_state(|s| async move { s.unroutable_messages.lock().len() }).await;
+    assert_eq!(0, buffered);
+}
+
+// Reads messages from the stream until a Ping arrives.
+// Returns the hash of the routed message and the nonce of the Ping.
+async fn recv_ping(stream: &mut Stream) -> (CryptoHash, u64) {
+    loop {
+        if let PeerMessage::Routed(msg) = stream.read().await.unwrap() {
+            if let RoutedMessageBody::Ping(ping) = &msg.body {
+                return (msg.hash(), ping.nonce);
+            }
+        }
+    }
+}
+
+// TIER1 connection which stops answering the liveness pings gets closed.
+#[tokio::test]
+async fn liveness_pings() {
+    init_test_logger();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+
+    let pm = start_pm(clock.clock(), TestDB::new(), chain.make_config(rng), chain.clone()).await;
+    let max_missed_pongs = pm.cfg.tier1.as_ref().unwrap().liveness_max_missed_pongs;
+    let cfg = chain.make_config(rng);
+    let chain_info = peer_manager::testonly::make_chain_info(&chain, &[&cfg]);
+    pm.set_chain_info(chain_info).await;
+
+    tracing::info!(target:"test", "Establish a TIER1 connection from a fake peer.");
+    let stream = tcp::Stream::connect(&pm.peer_info(), tcp::Tier::T1).await.unwrap();
+    let stream_id = stream.id();
+    let port = stream.local_addr.port();
+    let mut events = pm.events.from_now();
+    let mut stream = Stream::new(Some(Encoding::Proto), stream);
+    let vc = cfg.validator.clone().unwrap();
+    let handshake = Handshake {
+        protocol_version: PROTOCOL_VERSION,
+        oldest_supported_version: PROTOCOL_VERSION,
+        sender_peer_id: cfg.node_id(),
+        target_peer_id: pm.cfg.node_id(),
+        sender_listen_port: Some(port),
+        sender_chain_info: chain.get_peer_chain_info(),
+        partial_edge_info: PartialEdgeInfo::new(
+            &cfg.node_id(),
+            &pm.cfg.node_id(),
+            1,
+            &cfg.node_key,
+        ),
+        owned_account: Some(
+            OwnedAccount {
+                account_key: vc.signer.public_key().clone(),
+                peer_id: cfg.node_id(),
+                timestamp: clock.now_utc(),
+            }
+            .sign(vc.signer.as_ref()),
+        ),
+    };
+    stream.write(&PeerMessage::Tier1Handshake(handshake)).await;
+    events
+        .recv_until(|ev| match ev {
+            Event::PeerManager(PME::HandshakeCompleted(ev)) if ev.stream_id == stream_id => {
+                Some(())
+            }
+            _ => None,
+        })
+        .await;
+
+    tracing::info!(target:"test", "The fake peer answers the pings.");
+    for _ in 0..2 * max_missed_pongs {
+        clock.advance(time::Duration::seconds(30));
+        pm.tier1_check_liveness(&clock.clock()).await;
+        let (hash, nonce) = recv_ping(&mut stream).await;
+        clock.advance(time::Duration::milliseconds(100));
+        let pong = RawRoutedMessage {
+            target: PeerIdOrHash::Hash(hash),
+            body: RoutedMessageBody::Pong(Pong { nonce, source: cfg.node_id() }),
+        }
+        .sign(&cfg.node_key, 100, Some(clock.now_utc()));
+        stream.write(&PeerMessage::Routed(Box::new(pong))).await;
+        events
+            .recv_until(|ev| match ev {
+                Event::PeerManager(PME::Pong(pong)) if pong.nonce == nonce => Some(()),
+                _ => None,
+            })
+            .await;
+    }
+    let peer_id = cfg.node_id();
+    let rtt = pm.with_state(move |s| async move { s.rtt.lock().get(&peer_id) }).await;
+    assert_eq!(Some(time::Duration::milliseconds(100)), rtt);
+
+    tracing::info!(target:"test", "The fake peer stops answering the pings.");
+    for _ in 1..max_missed_pongs {
+        clock.advance(time::Duration::seconds(30));
+        pm.tier1_check_liveness(&clock.clock()).await;
+        recv_ping(&mut stream).await;
+    }
+    clock.advance(time::Duration::seconds(30));
+    pm.tier1_check_liveness(&clock.clock()).await;
+    let reason = events
+        .recv_until(|ev| match ev {
+            Event::PeerManager(PME::ConnectionClosed(ev)) if ev.stream_id == stream_id => {
+                Some(ev.reason)
+            }
+            _ => None,
+        })
+        .await;
+    assert_eq!(ClosingReason::PeerManagerRequest, reason);
+}
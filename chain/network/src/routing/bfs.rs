@@ -1,4 +1,5 @@
 use crate::peer_manager::peer_manager_actor::MAX_TIER2_PEERS;
+use crate::stats::metrics;
 use near_primitives::network::PeerId;
 use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet, VecDeque};
@@ -102,8 +103,13 @@ impl Graph {
         }
     }
 
+    /// Adds an undirected edge between `peer0` and `peer1`.
+    /// Self-loops are ignored and adding an already existing edge is a no-op.
     pub fn add_edge(&mut self, peer0: &PeerId, peer1: &PeerId) {
-        assert_ne!(peer0, peer1);
+        if peer0 == peer1 {
+            warn!(target: "network", ?peer0, "Ignoring a self-loop edge");
+            return;
+        }
         if !self.contains_edge(peer0, peer1) {
             let id0 = self.get_id(peer0);
             let id1 = self.get_id(peer1);
@@ -115,8 +121,12 @@ impl Graph {
         }
     }
 
+    /// Removes the edge between `peer0` and `peer1`.
+    /// Removing a self-loop or an edge which is not in the graph is a no-op.
     pub fn remove_edge(&mut self, peer0: &PeerId, peer1: &PeerId) {
-        assert_ne!(peer0, peer1);
+        if peer0 == peer1 {
+            return;
+        }
         if self.contains_edge(peer0, peer1) {
             let id0 = self.get_id(peer0);
             let id1 = self.get_id(peer1);
@@ -134,10 +144,25 @@ impl Graph {
     /// Compute for every node `u` on the graph (other than `source`) which are the neighbors of
     /// `sources` which belong to the shortest path from `source` to `u`. Nodes that are
     /// not connected to `source` will not appear in the result.
+    /// If `max_hops` is set, nodes further than `max_hops` from `source` are not explored and
+    /// don't appear in the result either. Direct neighbors of `source` are always included.
+    #[cfg(test)]
     pub fn calculate_distance(
         &self,
         unreliable_peers: &HashSet<PeerId>,
+        max_hops: Option<u32>,
     ) -> HashMap<PeerId, Vec<PeerId>> {
+        self.calculate_distance_and_path_lengths(unreliable_peers, max_hops).0
+    }
+
+    /// Same as calculate_distance(), but additionally returns the distribution of the path
+    /// lengths: `path_lengths[i]` is the number of nodes in the result, which are exactly
+    /// `i` hops away from `source`.
+    pub fn calculate_distance_and_path_lengths(
+        &self,
+        unreliable_peers: &HashSet<PeerId>,
+        max_hops: Option<u32>,
+    ) -> (HashMap<PeerId, Vec<PeerId>>, Vec<u64>) {
         // TODO add removal of unreachable nodes
 
         let unreliable_peers: HashSet<_> =
@@ -163,9 +188,21 @@ impl Graph {
             }
         }
 
+        let mut beyond_max_hops = 0;
         while let Some(cur_peer) = queue.pop_front() {
             let cur_distance = distance[cur_peer as usize];
 
+            if max_hops.map_or(false, |max_hops| cur_distance as u32 >= max_hops) {
+                // Don't expand further, just mark the nodes one hop away as visited,
+                // so that they are counted only once.
+                for &neighbor in &self.adjacency[cur_peer as usize] {
+                    if distance[neighbor as usize] == -1 {
+                        distance[neighbor as usize] = cur_distance + 1;
+                        beyond_max_hops += 1;
+                    }
+                }
+                continue;
+            }
             for &neighbor in &self.adjacency[cur_peer as usize] {
                 if distance[neighbor as usize] == -1 {
                     distance[neighbor as usize] = cur_distance + 1;
@@ -179,18 +216,120 @@ impl Graph {
             }
         }
 
+        metrics::PEER_BEYOND_MAX_ROUTE_HOPS.set(beyond_max_hops);
+
         // This takes 75% of the total time computation time of this function.
         self.compute_result(&routes, &distance)
     }
 
+    /// Computes up to `k` shortest loopless paths from `source` to `target` (Yen's algorithm),
+    /// ordered by length. Every path lists the peers on the way, excluding `source` and
+    /// including `target`. Unlike calculate_distance(), it doesn't skip the unreliable peers.
+    /// O(k * n * (n + m)). It is compiled only for tests, there is no production caller.
+    #[cfg(test)]
+    pub fn k_shortest_paths(&self, target: &PeerId, k: usize) -> Vec<Vec<PeerId>> {
+        let target = match self.p2id.get(target) {
+            Some(&id) if id != self.source_id && k > 0 => id,
+            _ => return vec![],
+        };
+        let mut paths: Vec<Vec<u32>> = vec![];
+        let mut candidates: Vec<Vec<u32>> = vec![];
+        let no_nodes = vec![false; self.id2p.len()];
+        if let Some(path) = self.shortest_path(self.source_id, target, &no_nodes, &HashSet::new()) {
+            paths.push(path);
+        }
+        while !paths.is_empty() && paths.len() < k {
+            let prev = &paths[paths.len() - 1];
+            for i in 0..prev.len() - 1 {
+                let root = &prev[..=i];
+                // Edges leaving the root, which are already used by the found paths.
+                let mut banned_edges = HashSet::new();
+                for path in &paths {
+                    if path.len() > i + 1 && &path[..=i] == root {
+                        banned_edges.insert(Self::edge_key(path[i], path[i + 1]));
+                    }
+                }
+                // Root nodes other than the spur node, to keep the paths loopless.
+                let mut banned_nodes = no_nodes.clone();
+                for &id in &root[..i] {
+                    banned_nodes[id as usize] = true;
+                }
+                if let Some(spur) =
+                    self.shortest_path(root[i], target, &banned_nodes, &banned_edges)
+                {
+                    let path: Vec<_> = root[..i].iter().copied().chain(spur).collect();
+                    if !paths.contains(&path) && !candidates.contains(&path) {
+                        candidates.push(path);
+                    }
+                }
+            }
+            // Pick the shortest candidate, the earliest one among the equally long ones.
+            let best = match candidates.iter().enumerate().min_by_key(|(_, p)| p.len()) {
+                Some((i, _)) => i,
+                None => break,
+            };
+            paths.push(candidates.remove(best));
+        }
+        paths
+            .into_iter()
+            .map(|path| path[1..].iter().map(|&id| self.id2p[id as usize].clone()).collect())
+            .collect()
+    }
+
+    #[cfg(test)]
+    fn edge_key(id0: u32, id1: u32) -> (u32, u32) {
+        (id0.min(id1), id0.max(id1))
+    }
+
+    /// BFS from `from` to `to`, avoiding the banned nodes and edges.
+    /// Returns the path including both ends, or None if `to` is unreachable.
+    #[cfg(test)]
+    fn shortest_path(
+        &self,
+        from: u32,
+        to: u32,
+        banned_nodes: &[bool],
+        banned_edges: &HashSet<(u32, u32)>,
+    ) -> Option<Vec<u32>> {
+        let mut parent: HashMap<u32, u32> = HashMap::from([(from, from)]);
+        let mut queue = VecDeque::from([from]);
+        while let Some(cur) = queue.pop_front() {
+            if cur == to {
+                let mut path = vec![to];
+                while path[path.len() - 1] != from {
+                    path.push(parent[&path[path.len() - 1]]);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for &neighbor in &self.adjacency[cur as usize] {
+                if banned_nodes[neighbor as usize]
+                    || banned_edges.contains(&Self::edge_key(cur, neighbor))
+                    || parent.contains_key(&neighbor)
+                {
+                    continue;
+                }
+                parent.insert(neighbor, cur);
+                queue.push_back(neighbor);
+            }
+        }
+        None
+    }
+
     /// Converts representation of the result, from an array representation, to
     /// a hashmap of PeerId -> Vec<PeerIds>
     /// Arguments:
     ///   - routes - for node given node at index `i`, give list of connected peers, which
     ///     are on the optimal path
-    ///   - distances - not really needed: TODO remove this argument
-    fn compute_result(&self, routes: &[u128], distance: &[i32]) -> HashMap<PeerId, Vec<PeerId>> {
+    ///   - distances - for node at index `i`, its distance from the source; used to compute
+    ///     the distribution of the path lengths
+    fn compute_result(
+        &self,
+        routes: &[u128],
+        distance: &[i32],
+    ) -> (HashMap<PeerId, Vec<PeerId>>, Vec<u64>) {
         let mut res = HashMap::with_capacity(routes.len());
+        let mut path_lengths = vec![];
 
         let neighbors = &self.adjacency[self.source_id as usize];
         let mut unreachable_nodes = 0;
@@ -217,11 +356,16 @@ impl Graph {
                 .map(|(_, &neighbor)| self.id2p[neighbor as usize].clone())
                 .collect();
             res.insert(self.id2p[key].clone(), peer_set);
+            let len = distance[key] as usize;
+            if path_lengths.len() <= len {
+                path_lengths.resize(len + 1, 0);
+            }
+            path_lengths[len] += 1;
         }
         if unreachable_nodes > 1000 {
             warn!("We store more than 1000 unreachable nodes: {}", unreachable_nodes);
         }
-        res
+        (res, path_lengths)
     }
 }
 
@@ -229,6 +373,7 @@ impl Graph {
 mod test {
     use super::Graph;
     use crate::test_utils::{expected_routing_tables, random_peer_id};
+    use near_primitives::network::PeerId;
     use std::collections::HashSet;
     use std::ops::Not;
 
@@ -262,6 +407,102 @@ mod test {
         assert_eq!(0, graph.compute_total_active_edges() as usize);
     }
 
+    #[test]
+    fn self_edge() {
+        let source = random_peer_id();
+        let node0 = random_peer_id();
+
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&node0, &node0);
+        graph.add_edge(&source, &source);
+        assert!(graph.contains_edge(&node0, &node0).not());
+        assert_eq!(0, graph.total_active_edges() as usize);
+        assert_eq!(0, graph.compute_total_active_edges() as usize);
+
+        graph.remove_edge(&node0, &node0);
+        graph.remove_edge(&source, &source);
+        assert_eq!(0, graph.total_active_edges() as usize);
+        assert!(expected_routing_tables(&graph.calculate_distance(&HashSet::new(), None), &[]));
+    }
+
+    #[test]
+    fn duplicate_edges() {
+        let source = random_peer_id();
+        let node0 = random_peer_id();
+        let node1 = random_peer_id();
+
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &node0);
+        graph.add_edge(&source, &node0);
+        graph.add_edge(&node0, &source);
+        assert_eq!(1, graph.total_active_edges() as usize);
+        assert_eq!(1, graph.compute_total_active_edges() as usize);
+
+        // Removing an edge which was never added doesn't affect the count.
+        graph.remove_edge(&node0, &node1);
+        assert_eq!(1, graph.total_active_edges() as usize);
+
+        graph.remove_edge(&source, &node0);
+        graph.remove_edge(&node0, &source);
+        assert_eq!(0, graph.total_active_edges() as usize);
+        assert_eq!(0, graph.compute_total_active_edges() as usize);
+        assert!(expected_routing_tables(&graph.calculate_distance(&HashSet::new(), None), &[]));
+    }
+
+    #[test]
+    fn max_hops() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..10).map(|_| random_peer_id()).collect();
+
+        // s - 0 - 1 - ... - 9
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &nodes[0]);
+        for i in 1..nodes.len() {
+            graph.add_edge(&nodes[i - 1], &nodes[i]);
+        }
+        let next_hops = |n: usize| -> Vec<_> {
+            (0..n).map(|i| (nodes[i].clone(), vec![nodes[0].clone()])).collect()
+        };
+
+        assert!(expected_routing_tables(
+            &graph.calculate_distance(&HashSet::new(), None),
+            &next_hops(10)
+        ));
+        assert!(expected_routing_tables(
+            &graph.calculate_distance(&HashSet::new(), Some(3)),
+            &next_hops(3)
+        ));
+        // Direct neighbors are reachable regardless of the limit.
+        assert!(expected_routing_tables(
+            &graph.calculate_distance(&HashSet::new(), Some(0)),
+            &next_hops(1)
+        ));
+    }
+
+    #[test]
+    fn path_lengths() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..5).map(|_| random_peer_id()).collect();
+
+        // s - 0 - 1 - 2 - 3
+        //  \- 4 -/
+        let mut graph = Graph::new(source.clone());
+        graph.add_edge(&source, &nodes[0]);
+        graph.add_edge(&source, &nodes[4]);
+        graph.add_edge(&nodes[4], &nodes[1]);
+        for i in 1..4 {
+            graph.add_edge(&nodes[i - 1], &nodes[i]);
+        }
+        let (_, path_lengths) = graph.calculate_distance_and_path_lengths(&HashSet::new(), None);
+        assert_eq!(vec![0, 2, 1, 1, 1], path_lengths);
+        let (_, path_lengths) = graph.calculate_distance_and_path_lengths(&HashSet::new(), Some(2));
+        assert_eq!(vec![0, 2, 1], path_lengths);
+
+        let graph = Graph::new(source);
+        let (_, path_lengths) = graph.calculate_distance_and_path_lengths(&HashSet::new(), None);
+        assert!(path_lengths.is_empty());
+    }
+
     #[test]
     fn graph_distance0() {
         let source = random_peer_id();
@@ -273,7 +514,7 @@ mod test {
         graph.add_edge(&source, &node0);
 
         assert!(expected_routing_tables(
-            &graph.calculate_distance(&HashSet::new()),
+            &graph.calculate_distance(&HashSet::new(), None),
             &[(node0.clone(), vec![node0.clone()])],
         ));
 
@@ -292,7 +533,7 @@ mod test {
         graph.add_edge(&nodes[2], &nodes[1]);
         graph.add_edge(&nodes[1], &nodes[2]);
 
-        assert!(expected_routing_tables(&graph.calculate_distance(&HashSet::new()), &[]));
+        assert!(expected_routing_tables(&graph.calculate_distance(&HashSet::new(), None), &[]));
 
         assert_eq!(2, graph.total_active_edges() as usize);
         assert_eq!(2, graph.compute_total_active_edges() as usize);
@@ -311,7 +552,7 @@ mod test {
         graph.add_edge(&source, &nodes[0]);
 
         assert!(expected_routing_tables(
-            &graph.calculate_distance(&HashSet::new()),
+            &graph.calculate_distance(&HashSet::new(), None),
             &[
                 (nodes[0].clone(), vec![nodes[0].clone()]),
                 (nodes[1].clone(), vec![nodes[0].clone()]),
@@ -336,7 +577,7 @@ mod test {
         graph.add_edge(&source, &nodes[1]);
 
         assert!(expected_routing_tables(
-            &graph.calculate_distance(&HashSet::new()),
+            &graph.calculate_distance(&HashSet::new(), None),
             &[
                 (nodes[0].clone(), vec![nodes[0].clone()]),
                 (nodes[1].clone(), vec![nodes[1].clone()]),
@@ -388,7 +629,10 @@ mod test {
             next_hops.push((node.clone(), target.clone()));
         }
 
-        assert!(expected_routing_tables(&graph.calculate_distance(&HashSet::new()), &next_hops));
+        assert!(expected_routing_tables(
+            &graph.calculate_distance(&HashSet::new(), None),
+            &next_hops
+        ));
 
         assert_eq!(22, graph.total_active_edges() as usize);
         assert_eq!(22, graph.compute_total_active_edges() as usize);
@@ -427,12 +671,64 @@ mod test {
             next_hops.push((node.clone(), target.clone()));
         }
 
-        assert!(expected_routing_tables(&graph.calculate_distance(&unreliable_peers), &next_hops));
+        assert!(expected_routing_tables(
+            &graph.calculate_distance(&unreliable_peers, None),
+            &next_hops
+        ));
 
         assert_eq!(22, graph.total_active_edges() as usize);
         assert_eq!(22, graph.compute_total_active_edges() as usize);
     }
 
+    /// Test the following grid
+    /// s - 0 - 1
+    /// |   |   |
+    /// 2 - 3 - 4
+    /// |   |   |
+    /// 5 - 6 - 7
+    ///
+    /// There are 12 loopless paths from s to 7: 6 of length 4, 4 of length 6 and 2 of length 8.
+    #[test]
+    fn k_shortest_paths() {
+        let source = random_peer_id();
+        let nodes: Vec<_> = (0..8).map(|_| random_peer_id()).collect();
+        let grid: Vec<&PeerId> = std::iter::once(&source).chain(nodes.iter()).collect();
+
+        let mut graph = Graph::new(source.clone());
+        for row in 0..3 {
+            for col in 0..3 {
+                let i = row * 3 + col;
+                if col < 2 {
+                    graph.add_edge(grid[i], grid[i + 1]);
+                }
+                if row < 2 {
+                    graph.add_edge(grid[i], grid[i + 3]);
+                }
+            }
+        }
+
+        let paths = graph.k_shortest_paths(&nodes[7], 100);
+        let lens: Vec<_> = paths.iter().map(|p| p.len()).collect();
+        assert_eq!(vec![4, 4, 4, 4, 4, 4, 6, 6, 6, 6, 8, 8], lens);
+        for path in &paths {
+            assert_eq!(Some(&nodes[7]), path.last());
+            let distinct: HashSet<_> = path.iter().collect();
+            assert_eq!(path.len(), distinct.len());
+            assert!(!distinct.contains(&source));
+        }
+        let distinct: HashSet<_> = paths.iter().collect();
+        assert_eq!(paths.len(), distinct.len());
+
+        assert_eq!(
+            lens[..3].to_vec(),
+            graph.k_shortest_paths(&nodes[7], 3).iter().map(|p| p.len()).collect::<Vec<_>>()
+        );
+        assert_eq!(vec![vec![nodes[0].clone()]], graph.k_shortest_paths(&nodes[0], 1));
+        assert!(graph.k_shortest_paths(&nodes[7], 0).is_empty());
+        assert!(graph.k_shortest_paths(&source, 5).is_empty());
+        assert!(graph.k_shortest_paths(&random_peer_id(), 5).is_empty());
+    }
+
     // Test looks like this:
     // s - 0 ----- 1
     //  \--2 - 3 --/
@@ -457,6 +753,9 @@ mod test {
             (nodes[2].clone(), vec![nodes[2].clone()]),
             (nodes[3].clone(), vec![nodes[2].clone()]),
         ];
-        assert!(expected_routing_tables(&graph.calculate_distance(&unreliable_peers), &next_hops));
+        assert!(expected_routing_tables(
+            &graph.calculate_distance(&unreliable_peers, None),
+            &next_hops
+        ));
     }
 }
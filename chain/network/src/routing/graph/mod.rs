@@ -10,8 +10,9 @@ use near_async::time;
 use near_primitives::network::PeerId;
 use parking_lot::Mutex;
 use rayon::iter::ParallelBridge;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
 
 #[cfg(test)]
 mod tests;
@@ -20,11 +21,59 @@ mod tests;
 type EdgeKey = (PeerId, PeerId);
 pub type NextHopTable = HashMap<PeerId, Vec<PeerId>>;
 
+/// Number of the most recent routing table recomputations,
+/// which are taken into account by `Graph::recalc_latency_stats()`.
+const RECALC_LATENCY_WINDOW: usize = 1000;
+
+/// Reachability score of a peer, which has been reachable for long enough.
+pub const MAX_REACHABILITY_SCORE: u32 = 100;
+
 #[derive(Clone)]
 pub struct GraphConfig {
     pub node_id: PeerId,
     pub prune_unreachable_peers_after: time::Duration,
+    /// If set, the unreachable peers are pruned at most that often, rather than on every update.
+    /// Has to be positive.
+    pub prune_interval: Option<time::Duration>,
+    /// If set, peers further than that many hops away are considered unreachable.
+    /// Bounds the cost of recomputing the routing table on large graphs.
+    pub max_route_hops: Option<u32>,
+    /// If set, every peer gets a reachability score in [0,MAX_REACHABILITY_SCORE].
+    /// Score of an unreachable peer decays linearly over prune_unreachable_peers_after,
+    /// so that it hits 0 when the peer gets pruned. Score of a reachable peer recovers
+    /// by that much on every update. Next hops with higher scores are preferred for routing.
+    pub reachability_recovery: Option<u32>,
     pub prune_edges_after: Option<time::Duration>,
+    /// Maximal number of edges kept in memory. Once reached, a new edge is accepted only
+    /// if it is newer than the oldest non-local edge, which then gets evicted.
+    /// Edges adjacent to node_id are never evicted and always accepted.
+    pub max_edges: Option<usize>,
+    /// If set, the recent state transitions of edges are recorded.
+    pub edge_history: Option<EdgeHistoryConfig>,
+    /// Maximal number of edges verified in a single rayon task.
+    /// Larger inputs are verified in batches, so that they don't monopolize the rayon pool.
+    pub verify_batch_size: usize,
+    /// If set, the routing table is checked for consistency with the graph after every update
+    /// and the violations are logged (see `Graph::self_check()`). It is expensive, so it is
+    /// meant for tests and debugging.
+    pub self_check: bool,
+}
+
+#[derive(Clone)]
+pub struct EdgeHistoryConfig {
+    /// Number of the most recent transitions kept per edge.
+    pub len: usize,
+    /// If false, only the edges adjacent to node_id are tracked, which bounds the memory usage.
+    pub all_edges: bool,
+}
+
+/// A recorded update of an edge.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EdgeTransition {
+    pub nonce: u64,
+    pub state: EdgeState,
+    /// Time at which the update has been added to the graph.
+    pub at: time::Utc,
 }
 
 #[derive(Default)]
@@ -32,6 +81,178 @@ pub struct GraphSnapshot {
     pub edges: im::HashMap<EdgeKey, Edge>,
     pub local_edges: HashMap<PeerId, Edge>,
     pub next_hops: Arc<NextHopTable>,
+    /// Number of edges in the graph, including the removed ones.
+    pub total_edges: usize,
+    /// Number of active edges in the graph.
+    pub active_edges: u64,
+    /// Number of peers reachable from node_id.
+    pub reachable_peer_count: usize,
+    /// Reachability scores of the peers (see `GraphConfig::reachability_recovery`).
+    /// Empty if the scores are disabled.
+    pub reachability_scores: Arc<HashMap<PeerId, u32>>,
+    /// path_lengths[i] is the number of reachable peers exactly i hops away from node_id.
+    pub path_lengths: Vec<u64>,
+}
+
+/// Difference between two GraphSnapshots.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct GraphDiff {
+    /// Edges which are new or have been updated (i.e. have a higher nonce).
+    pub added_edges: Vec<Edge>,
+    /// Edges which are not in the graph any more (i.e. have been pruned or evicted).
+    pub removed_edges: Vec<Edge>,
+    /// Peers whose next hops have changed, including the peers which became
+    /// reachable or unreachable.
+    pub changed_next_hops: Vec<PeerId>,
+}
+
+impl GraphDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_edges.is_empty()
+            && self.removed_edges.is_empty()
+            && self.changed_next_hops.is_empty()
+    }
+}
+
+impl GraphSnapshot {
+    /// Statistics of the hop counts to the reachable peers.
+    /// None if there are no reachable peers.
+    pub fn path_length_stats(&self) -> Option<PathLengthStats> {
+        PathLengthStats::new(&self.path_lengths)
+    }
+
+    /// Computes the changes between `prev` and `self`. O(n) in the size of both snapshots,
+    /// unless the edges are shared structurally, which is detected in O(1).
+    pub fn diff(&self, prev: &GraphSnapshot) -> GraphDiff {
+        let mut diff = GraphDiff::default();
+        if !self.edges.ptr_eq(&prev.edges) {
+            for (key, edge) in &self.edges {
+                if prev.edges.get(key) != Some(edge) {
+                    diff.added_edges.push(edge.clone());
+                }
+            }
+            for (key, edge) in &prev.edges {
+                if !self.edges.contains_key(key) {
+                    diff.removed_edges.push(edge.clone());
+                }
+            }
+        }
+        if !Arc::ptr_eq(&self.next_hops, &prev.next_hops) {
+            for (peer_id, hops) in self.next_hops.iter() {
+                if prev.next_hops.get(peer_id) != Some(hops) {
+                    diff.changed_next_hops.push(peer_id.clone());
+                }
+            }
+            for peer_id in prev.next_hops.keys() {
+                if !self.next_hops.contains_key(peer_id) {
+                    diff.changed_next_hops.push(peer_id.clone());
+                }
+            }
+        }
+        diff
+    }
+}
+
+/// Latency percentiles of the recent routing table recomputations.
+/// Complements the ROUTING_TABLE_RECALCULATION_HISTOGRAM metric, which cannot be queried
+/// in-process.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LatencyStats {
+    /// Number of recomputations taken into account.
+    pub samples: usize,
+    pub p50: time::Duration,
+    pub p99: time::Duration,
+    pub max: time::Duration,
+}
+
+impl LatencyStats {
+    /// Computes the stats of the given samples. Returns None if there are no samples.
+    fn new<'a>(samples: impl Iterator<Item = &'a time::Duration>) -> Option<Self> {
+        let mut samples: Vec<_> = samples.copied().collect();
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort();
+        // Nearest-rank percentile.
+        let percentile = |p: usize| samples[((samples.len() * p + 99) / 100).max(1) - 1];
+        Some(Self {
+            samples: samples.len(),
+            p50: percentile(50),
+            p99: percentile(99),
+            max: samples[samples.len() - 1],
+        })
+    }
+}
+
+/// Violation of an invariant between the graph and the routing table,
+/// reported by `Graph::self_check()`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Inconsistency {
+    /// `next_hop` of `peer_id` is not connected to node_id via an active edge.
+    NextHopNotLocal { peer_id: PeerId, next_hop: PeerId },
+    /// Peer has been reachable at the last update, but no edge is adjacent to it.
+    ReachablePeerNotInGraph(PeerId),
+    /// Peer has next hops, but it hasn't been marked as reachable at the last update.
+    RoutablePeerNotReachable(PeerId),
+    /// node_id has next hops assigned.
+    NextHopsForSelf,
+    /// Edge connects a peer to itself.
+    SelfLoop(EdgeKey),
+}
+
+impl std::fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NextHopNotLocal { peer_id, next_hop } => {
+                write!(f, "next hop {next_hop} of {peer_id} is not an active local edge")
+            }
+            Self::ReachablePeerNotInGraph(peer_id) => {
+                write!(f, "reachable peer {peer_id} has no edges")
+            }
+            Self::RoutablePeerNotReachable(peer_id) => {
+                write!(f, "peer {peer_id} has next hops, but is not marked as reachable")
+            }
+            Self::NextHopsForSelf => write!(f, "node_id has next hops"),
+            Self::SelfLoop((peer_id, _)) => write!(f, "edge from {peer_id} to itself"),
+        }
+    }
+}
+
+/// Statistics of the hop counts to the reachable peers, see `GraphSnapshot::path_lengths`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathLengthStats {
+    /// Number of peers taken into account.
+    pub peers: u64,
+    pub p50: u32,
+    pub p99: u32,
+    pub max: u32,
+}
+
+impl PathLengthStats {
+    fn new(path_lengths: &[u64]) -> Option<Self> {
+        let peers: u64 = path_lengths.iter().sum();
+        if peers == 0 {
+            return None;
+        }
+        // Nearest-rank percentile.
+        let percentile = |p: u64| {
+            let rank = ((peers * p + 99) / 100).max(1);
+            let mut seen = 0;
+            for (len, n) in path_lengths.iter().enumerate() {
+                seen += n;
+                if seen >= rank {
+                    return len as u32;
+                }
+            }
+            unreachable!()
+        };
+        Some(Self {
+            peers,
+            p50: percentile(50),
+            p99: percentile(99),
+            max: path_lengths.iter().rposition(|n| *n > 0).unwrap() as u32,
+        })
+    }
 }
 
 struct Inner {
@@ -43,8 +264,20 @@ struct Inner {
     graph: bfs::Graph,
 
     edges: im::HashMap<EdgeKey, Edge>,
+    /// Edges not adjacent to node_id, ordered by nonce: candidates for eviction
+    /// (see `GraphConfig::max_edges`). Maintained only if max_edges is set.
+    evictable: BTreeSet<(u64, EdgeKey)>,
     /// Last time a peer was reachable.
     peer_reachable_at: HashMap<PeerId, time::Instant>,
+    /// Reachability scores of the peers (see `GraphConfig::reachability_recovery`).
+    reachability_scores: Arc<HashMap<PeerId, u32>>,
+    /// Last time the unreachable peers have been pruned.
+    unreachable_peers_pruned_at: Option<time::Instant>,
+    /// Recent transitions of the tracked edges (see `GraphConfig::edge_history`).
+    /// History of an edge is dropped together with the edge.
+    edge_history: HashMap<EdgeKey, VecDeque<EdgeTransition>>,
+    /// Durations of the most recent routing table recomputations.
+    recalc_latency: VecDeque<time::Duration>,
     store: store::Store,
 }
 
@@ -52,6 +285,49 @@ fn has(set: &im::HashMap<EdgeKey, Edge>, edge: &Edge) -> bool {
     set.get(&edge.key()).map_or(false, |x| x.nonce() >= edge.nonce())
 }
 
+/// Drops the duplicates and the edges which are not newer than the ones in `snapshot`.
+/// It is a cheap pre-filter, executed before locking Inner, so that the locked section
+/// processes as few edges as possible. Inner::add_edges() still checks the edges against
+/// the current state, because the snapshot might be outdated.
+fn drop_known_edges(snapshot: &GraphSnapshot, edges: Vec<Edge>) -> Vec<Edge> {
+    let mut edges = Edge::deduplicate(edges);
+    edges.retain(|e| !has(&snapshot.edges, e));
+    edges
+}
+
+/// Verifies the edges in parallel on rayon, in batches of at most `batch_size` edges.
+/// Every batch is a separate rayon task, so other tasks can make progress in between.
+/// Stops after the first batch containing an invalid edge, or once `canceled` is triggered.
+/// Returns the valid edges verified so far and false iff an invalid edge has been found.
+fn verify_edges(
+    mut edges: Vec<Edge>,
+    batch_size: usize,
+    canceled: &CancellationToken,
+) -> (Vec<Edge>, bool) {
+    let _timer = metrics::EDGE_VERIFICATION_SECONDS.start_timer();
+    let mut verified = vec![];
+    while !edges.is_empty() && !canceled.is_cancelled() {
+        let rest = edges.split_off(batch_size.max(1).min(edges.len()));
+        let batch = std::mem::replace(&mut edges, rest);
+        // Stop at first invalid edge.
+        let (batch, ok) = concurrency::rayon::run_blocking(move || {
+            concurrency::rayon::try_map(batch.into_iter().par_bridge(), |e| {
+                if e.verify() {
+                    Some(e)
+                } else {
+                    metrics::EDGES_INVALID.inc();
+                    None
+                }
+            })
+        });
+        verified.extend(batch);
+        if !ok {
+            return (verified, false);
+        }
+    }
+    (verified, true)
+}
+
 impl Inner {
     /// Adds an edge without validating the signatures. O(1).
     /// Returns true, iff <edge> was newer than an already known version of this edge.
@@ -65,21 +341,87 @@ impl Inner {
                 return false;
             }
         }
+        if !self.edges.contains_key(edge.key()) && !self.make_room_for(&edge) {
+            metrics::EDGES_REJECTED.inc();
+            return false;
+        }
         let key = edge.key();
         // Add the edge.
         match edge.edge_type() {
             EdgeState::Active => self.graph.add_edge(&key.0, &key.1),
             EdgeState::Removed => self.graph.remove_edge(&key.0, &key.1),
         }
-        self.edges.insert(key.clone(), edge);
+        self.record_transition(now, &edge);
+        self.insert_edge(edge);
         true
     }
 
-    /// Removes an edge by key. O(1).
+    /// Inserts an edge, replacing the previous version. O(log n).
+    fn insert_edge(&mut self, edge: Edge) {
+        let key = edge.key().clone();
+        let evictable =
+            self.config.max_edges.is_some() && !edge.contains_peer(&self.config.node_id);
+        let nonce = edge.nonce();
+        if let Some(prev) = self.edges.insert(key.clone(), edge) {
+            self.evictable.remove(&(prev.nonce(), key.clone()));
+        }
+        if evictable {
+            self.evictable.insert((nonce, key));
+        }
+    }
+
+    /// Records the update of an edge, before it replaces the previous version. O(1).
+    fn record_transition(&mut self, now: time::Utc, edge: &Edge) {
+        let state = edge.edge_type();
+        if let Some(prev) = self.edges.get(edge.key()) {
+            if prev.edge_type() != state {
+                metrics::EDGE_FLAP_TOTAL.inc();
+            }
+        }
+        let cfg = match &self.config.edge_history {
+            Some(cfg) => cfg,
+            None => return,
+        };
+        if cfg.len == 0 || !(cfg.all_edges || edge.contains_peer(&self.config.node_id)) {
+            return;
+        }
+        let history = self.edge_history.entry(edge.key().clone()).or_default();
+        if history.len() == cfg.len {
+            history.pop_front();
+        }
+        history.push_back(EdgeTransition { nonce: edge.nonce(), state, at: now });
+    }
+
+    /// Enforces config.max_edges before inserting <edge> as a new key.
+    /// Evicts the oldest (by nonce) edge not adjacent to node_id if needed. O(log n).
+    /// Returns false iff <edge> should be rejected.
+    fn make_room_for(&mut self, edge: &Edge) -> bool {
+        let max_edges = match self.config.max_edges {
+            Some(max_edges) => max_edges,
+            None => return true,
+        };
+        if self.edges.len() < max_edges {
+            return true;
+        }
+        let is_local = edge.contains_peer(&self.config.node_id);
+        match self.evictable.iter().next() {
+            Some((nonce, key)) if is_local || *nonce < edge.nonce() => {
+                let key = key.clone();
+                self.remove_edge(&key);
+                true
+            }
+            // Local edges are accepted even if there is nothing left to evict.
+            _ => is_local,
+        }
+    }
+
+    /// Removes an edge by key. O(log n).
     fn remove_edge(&mut self, key: &EdgeKey) {
-        if self.edges.remove(key).is_some() {
+        if let Some(edge) = self.edges.remove(key) {
             self.graph.remove_edge(&key.0, &key.1);
+            self.evictable.remove(&(edge.nonce(), key.clone()));
         }
+        self.edge_history.remove(key);
     }
 
     /// Removes all edges adjacent to the peers from the set.
@@ -131,8 +473,9 @@ impl Inner {
     /// New component `C_3` will be created.
     /// And mapping from `C` to `C_2` will be overridden by mapping from `C` to `C_3`.
     /// And therefore `C_2` component will become unreachable.
-    /// TODO(gprusak): this whole algorithm seems to be leaking stuff to storage and never cleaning up.
-    /// What is the point of it? What does it actually gives us?
+    /// Components which no peer is a member of any more (like `C_2` above) are deleted
+    /// periodically, see `Graph::compact_components()`.
+    /// TODO(gprusak): What is the point of this whole algorithm? What does it actually gives us?
     fn load_component(&mut self, now: time::Utc, peer_id: PeerId) {
         if peer_id == self.config.node_id || self.peer_reachable_at.contains_key(&peer_id) {
             return;
@@ -144,6 +487,10 @@ impl Inner {
                 return;
             }
         };
+        if !edges.is_empty() {
+            tracing::debug!(target: "network", %peer_id, edges = edges.len(), "Component loaded from DB");
+            metrics::GRAPH_COMPONENTS.with_label_values(&["popped"]).inc();
+        }
         for e in edges {
             self.update_edge(now, e);
         }
@@ -178,10 +525,80 @@ impl Inner {
         // Prune edges from graph.
         let edges = self.remove_adjacent_edges(&peers);
 
+        metrics::PEER_PRUNED.inc_by(peers.len() as u64);
+
         // Store the pruned data in DB.
         if let Err(e) = self.store.push_component(&peers, &edges) {
             tracing::warn!("self.store.push_component(): {}", e);
+            return;
+        }
+        tracing::debug!(target: "network", peers = peers.len(), edges = edges.len(), "Component stored in DB");
+        metrics::GRAPH_COMPONENTS.with_label_values(&["pushed"]).inc();
+    }
+
+    /// Recovers the reachability scores of the peers reachable at `now` and decays
+    /// the scores of the unreachable ones. Peers missing from peer_reachable_at are dropped.
+    fn update_reachability_scores(&mut self, now: time::Instant, recovery: u32) {
+        let decay_period = self.config.prune_unreachable_peers_after;
+        let mut scores = HashMap::new();
+        for (peer_id, reachable_at) in &self.peer_reachable_at {
+            let old = self.reachability_scores.get(peer_id).copied();
+            let score = if *reachable_at == now {
+                // Newly discovered peers start with the max score.
+                old.map_or(MAX_REACHABILITY_SCORE, |s| {
+                    s.saturating_add(recovery).min(MAX_REACHABILITY_SCORE)
+                })
+            } else {
+                let left = (decay_period - (now - *reachable_at)).whole_nanoseconds().max(0);
+                let decayed =
+                    left * MAX_REACHABILITY_SCORE as i128 / decay_period.whole_nanoseconds().max(1);
+                let decayed = decayed as u32;
+                old.unwrap_or(MAX_REACHABILITY_SCORE).min(decayed)
+            };
+            scores.insert(peer_id.clone(), score);
+        }
+        self.reachability_scores = Arc::new(scores);
+    }
+
+    /// Checks the invariants between the graph and the `next_hops` computed from it. O(n).
+    fn self_check(&self, next_hops: &NextHopTable) -> Vec<Inconsistency> {
+        let node_id = &self.config.node_id;
+        let mut res = vec![];
+        let mut peers = HashSet::new();
+        for key in self.edges.keys() {
+            if key.0 == key.1 {
+                res.push(Inconsistency::SelfLoop(key.clone()));
+            }
+            peers.insert(&key.0);
+            peers.insert(&key.1);
+        }
+        let last_update = self.peer_reachable_at.get(node_id);
+        for (peer_id, hops) in next_hops {
+            if peer_id == node_id {
+                res.push(Inconsistency::NextHopsForSelf);
+                continue;
+            }
+            if last_update.is_none() || self.peer_reachable_at.get(peer_id) != last_update {
+                res.push(Inconsistency::RoutablePeerNotReachable(peer_id.clone()));
+            }
+            for next_hop in hops {
+                let local_edge = self.edges.get(&Edge::make_key(node_id.clone(), next_hop.clone()));
+                if !local_edge.map_or(false, |e| e.edge_type() == EdgeState::Active) {
+                    res.push(Inconsistency::NextHopNotLocal {
+                        peer_id: peer_id.clone(),
+                        next_hop: next_hop.clone(),
+                    });
+                }
+            }
         }
+        // Peers unreachable since the last update may legitimately lose all their edges
+        // before they get pruned, so only the peers reachable at the last update are checked.
+        for (peer_id, reachable_at) in &self.peer_reachable_at {
+            if peer_id != node_id && Some(reachable_at) == last_update && !peers.contains(peer_id) {
+                res.push(Inconsistency::ReachablePeerNotInGraph(peer_id.clone()));
+            }
+        }
+        res
     }
 
     /// Verifies edges, then adds them to the graph.
@@ -194,8 +611,15 @@ impl Inner {
     ///   verified so far (and returns them), but drops all the remaining ones. This way the
     ///   wasted work (verification of invalid edges) is constant, no matter how large the input
     ///   size is.
-    fn add_edges(&mut self, clock: &time::Clock, mut edges: Vec<Edge>) -> (Vec<Edge>, bool) {
-        metrics::EDGE_UPDATES.inc_by(edges.len() as u64);
+    ///
+    /// Verification stops early once `canceled` is triggered, then only the edges verified
+    /// so far are added.
+    fn add_edges(
+        &mut self,
+        clock: &time::Clock,
+        mut edges: Vec<Edge>,
+        canceled: &CancellationToken,
+    ) -> (Vec<Edge>, bool) {
         // Start with deduplicating the edges.
         // TODO(gprusak): sending duplicate edges should be considered a malicious behavior
         // instead, however that would be backward incompatible, so it can be introduced in
@@ -216,17 +640,7 @@ impl Inner {
         // Retain only new edges.
         edges.retain(|e| !has(&self.edges, e));
 
-        // Verify the edges in parallel on rayon.
-        // Stop at first invalid edge.
-        let (mut edges, ok) = concurrency::rayon::run_blocking(move || {
-            concurrency::rayon::try_map(edges.into_iter().par_bridge(), |e| {
-                if e.verify() {
-                    Some(e)
-                } else {
-                    None
-                }
-            })
-        });
+        let (mut edges, ok) = verify_edges(edges, self.config.verify_batch_size, canceled);
 
         // Add the verified edges to the graph.
         edges.retain(|e| self.update_edge(now, e.clone()));
@@ -234,29 +648,46 @@ impl Inner {
     }
 
     /// 1. Prunes expired edges.
-    /// 2. Prunes unreachable graph components.
+    /// 2. Prunes unreachable graph components (at most once per `prune_interval`, if set).
     /// 3. Recomputes GraphSnapshot.
     pub fn update(
         &mut self,
         clock: &time::Clock,
         unreliable_peers: &HashSet<PeerId>,
     ) -> GraphSnapshot {
-        let _update_time = metrics::ROUTING_TABLE_RECALCULATION_HISTOGRAM.start_timer();
+        let update_time = metrics::ROUTING_TABLE_RECALCULATION_HISTOGRAM.start_timer();
         // Update metrics after edge update
         if let Some(prune_edges_after) = self.config.prune_edges_after {
             self.prune_old_edges(clock.now_utc() - prune_edges_after);
         }
-        let next_hops = Arc::new(self.graph.calculate_distance(unreliable_peers));
+        let (next_hops, path_lengths) = self
+            .graph
+            .calculate_distance_and_path_lengths(unreliable_peers, self.config.max_route_hops);
+        let next_hops = Arc::new(next_hops);
+        metrics::set_routing_path_lengths(&path_lengths);
 
         // Update peer_reachable_at.
         let now = clock.now();
         self.peer_reachable_at.insert(self.config.node_id.clone(), now);
         for peer in next_hops.keys() {
-            self.peer_reachable_at.insert(peer.clone(), now);
+            if self.peer_reachable_at.insert(peer.clone(), now).is_none() {
+                metrics::PEER_REACHABLE_ADDED.inc();
+            }
         }
-        if let Some(unreachable_since) = now.checked_sub(self.config.prune_unreachable_peers_after)
+        if self
+            .unreachable_peers_pruned_at
+            .zip(self.config.prune_interval)
+            .map_or(true, |(t, interval)| now - t >= interval)
         {
-            self.prune_unreachable_peers(unreachable_since);
+            self.unreachable_peers_pruned_at = Some(now);
+            if let Some(unreachable_since) =
+                now.checked_sub(self.config.prune_unreachable_peers_after)
+            {
+                self.prune_unreachable_peers(unreachable_since);
+            }
+        }
+        if let Some(recovery) = self.config.reachability_recovery {
+            self.update_reachability_scores(now, recovery);
         }
         let mut local_edges = HashMap::new();
         for e in self.edges.clone().values() {
@@ -264,11 +695,28 @@ impl Inner {
                 local_edges.insert(other.clone(), e.clone());
             }
         }
+        let total_edges = self.edges.len();
+        let active_edges = self.graph.total_active_edges();
+        let reachable_peer_count = next_hops.len();
         metrics::ROUTING_TABLE_RECALCULATIONS.inc();
-        metrics::PEER_REACHABLE.set(next_hops.len() as i64);
-        metrics::EDGE_ACTIVE.set(self.graph.total_active_edges() as i64);
-        metrics::EDGE_TOTAL.set(self.edges.len() as i64);
-        GraphSnapshot { edges: self.edges.clone(), local_edges, next_hops }
+        metrics::PEER_REACHABLE.set(reachable_peer_count as i64);
+        metrics::EDGE_ACTIVE.set(active_edges as i64);
+        metrics::EDGE_TOTAL.set(total_edges as i64);
+        // The same wall clock duration as reported to the histogram.
+        if self.recalc_latency.len() == RECALC_LATENCY_WINDOW {
+            self.recalc_latency.pop_front();
+        }
+        self.recalc_latency.push_back(time::Duration::seconds_f64(update_time.stop_and_record()));
+        GraphSnapshot {
+            edges: self.edges.clone(),
+            local_edges,
+            next_hops,
+            total_edges,
+            active_edges,
+            reachable_peer_count,
+            reachability_scores: self.reachability_scores.clone(),
+            path_lengths,
+        }
     }
 }
 
@@ -279,35 +727,104 @@ pub(crate) struct Graph {
     // TODO(gprusak): RoutingTableView consists of a bunch of unrelated stateful features.
     // It requires a refactor.
     pub routing_table: RoutingTableView,
+    /// Used to delete the orphaned components without locking `inner`.
+    store: store::Store,
 
     runtime: Runtime,
 }
 
 impl Graph {
-    pub fn new(config: GraphConfig, store: store::Store) -> Self {
-        Self {
+    pub fn new(config: GraphConfig, store: store::Store) -> anyhow::Result<Self> {
+        if config.prune_interval.map_or(false, |interval| interval <= time::Duration::ZERO) {
+            anyhow::bail!("prune_interval has to be positive");
+        }
+        Ok(Self {
             routing_table: RoutingTableView::new(store.clone()),
             inner: Arc::new(Mutex::new(Inner {
                 graph: bfs::Graph::new(config.node_id.clone()),
                 config,
                 edges: Default::default(),
+                evictable: BTreeSet::new(),
                 peer_reachable_at: HashMap::new(),
-                store,
+                reachability_scores: Arc::default(),
+                unreachable_peers_pruned_at: None,
+                edge_history: HashMap::new(),
+                recalc_latency: VecDeque::new(),
+                store: store.clone(),
             })),
+            store,
             unreliable_peers: ArcSwap::default(),
             snapshot: ArcSwap::default(),
             runtime: Runtime::new(),
-        }
+        })
     }
 
     pub fn load(&self) -> Arc<GraphSnapshot> {
         self.snapshot.load_full()
     }
 
+    /// Recent transitions of the edge with the given key, oldest first.
+    /// Empty if the edge is not tracked (see `GraphConfig::edge_history`).
+    pub fn edge_history(&self, key: &EdgeKey) -> Vec<EdgeTransition> {
+        self.inner
+            .lock()
+            .edge_history
+            .get(key)
+            .map(|history| history.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Latency percentiles of the recent routing table recomputations.
+    /// None if the routing table hasn't been computed yet.
+    pub fn recalc_latency_stats(&self) -> Option<LatencyStats> {
+        LatencyStats::new(self.inner.lock().recalc_latency.iter())
+    }
+
+    /// Checks the consistency between the graph and the current routing table.
+    /// Returns the list of detected violations, which is empty if everything is fine.
+    /// If `GraphConfig::self_check` is set, it is executed after every update.
+    #[cfg(test)]
+    pub fn self_check(&self) -> Vec<Inconsistency> {
+        let inner = self.inner.lock();
+        inner.self_check(&self.load().next_hops)
+    }
+
+    /// Up to `k` shortest loopless paths from node_id to `peer_id` in the current graph,
+    /// see `bfs::Graph::k_shortest_paths()`. Test-only: it holds the graph lock for the
+    /// whole computation, which is too expensive to expose on a running node.
+    #[cfg(test)]
+    pub fn paths_to(&self, peer_id: &PeerId, k: usize) -> Vec<Vec<PeerId>> {
+        self.inner.lock().graph.k_shortest_paths(peer_id, k)
+    }
+
+    /// Deletes the orphaned components from DB (see `Store::compact_components()`).
+    /// It doesn't lock the graph, so it doesn't delay the routing table updates.
+    /// We call this function every COMPONENTS_COMPACTION_INTERVAL from peer_manager_actor.rs.
+    pub async fn compact_components(&self) {
+        let mut store = self.store.clone();
+        self.runtime
+            .handle
+            .spawn_blocking(move || match store.compact_components() {
+                Ok(n) => metrics::GRAPH_COMPONENTS.with_label_values(&["deleted"]).inc_by(n as u64),
+                Err(e) => tracing::warn!("store.compact_components(): {}", e),
+            })
+            .await
+            .unwrap()
+    }
+
     pub fn set_unreliable_peers(&self, unreliable_peers: HashSet<PeerId>) {
         self.unreliable_peers.store(Arc::new(unreliable_peers));
     }
 
+    /// Peers excluded from routing, as set by the last `set_unreliable_peers()` call.
+    pub fn unreliable_peers(&self) -> Arc<HashSet<PeerId>> {
+        self.unreliable_peers.load_full()
+    }
+
+    pub fn is_unreliable(&self, peer_id: &PeerId) -> bool {
+        self.unreliable_peers.load().contains(peer_id)
+    }
+
     /// Verifies, then adds edges to the graph, then recomputes the routing table.
     /// Each entry of `edges` are edges coming from a different source.
     /// Returns (new_edges,oks) where
@@ -332,8 +849,14 @@ impl Graph {
         // TODO(gprusak): It would be better to move CPU heavy stuff to rayon and make DB calls async,
         // but that will require further refactor. Or even better: get rid of the Graph all
         // together.
+        metrics::EDGE_UPDATES.inc_by(edges.iter().map(|es| es.len() as u64).sum());
+        let snapshot = self.load();
+        let edges: Vec<_> = edges.into_iter().map(|es| drop_known_edges(&snapshot, es)).collect();
         let this = self.clone();
         let clock = clock.clone();
+        // Edge verification is interrupted as soon as this future is dropped.
+        let canceled = CancellationToken::new();
+        let _cancel_on_drop = canceled.clone().drop_guard();
         self.runtime
             .handle
             .spawn_blocking(move || {
@@ -341,12 +864,37 @@ impl Graph {
                 let mut new_edges = vec![];
                 let mut oks = vec![];
                 for es in edges {
-                    let (es, ok) = inner.add_edges(&clock, es);
+                    let (es, ok) = inner.add_edges(&clock, es, &canceled);
                     oks.push(ok);
                     new_edges.extend(es);
                 }
-                let snapshot = inner.update(&clock, &this.unreliable_peers.load());
+                let snapshot = inner.update(&clock, &this.unreliable_peers());
+                // Computing the diff is O(n), so it is done only if it is going to be logged.
+                if tracing::enabled!(target: "network", tracing::Level::DEBUG) {
+                    let diff = snapshot.diff(&this.snapshot.load());
+                    if !diff.is_empty() {
+                        tracing::debug!(target: "network",
+                            added_edges = diff.added_edges.len(),
+                            removed_edges = diff.removed_edges.len(),
+                            changed_next_hops = diff.changed_next_hops.len(),
+                            "Routing table updated");
+                        if let Some(stats) = snapshot.path_length_stats() {
+                            tracing::debug!(target: "network",
+                                peers = stats.peers,
+                                p50 = stats.p50,
+                                p99 = stats.p99,
+                                max = stats.max,
+                                "Path lengths to the reachable peers");
+                        }
+                    }
+                }
+                if inner.config.self_check {
+                    for inconsistency in inner.self_check(&snapshot.next_hops) {
+                        tracing::error!(target: "network", %inconsistency, "Routing table inconsistent with the graph");
+                    }
+                }
                 let snapshot = Arc::new(snapshot);
+                this.routing_table.set_reachability_scores(snapshot.reachability_scores.clone());
                 this.routing_table.update(snapshot.next_hops.clone());
                 this.snapshot.store(snapshot);
                 (new_edges, oks)
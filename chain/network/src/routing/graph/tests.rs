@@ -1,7 +1,13 @@
-use super::{Graph, GraphConfig};
+use super::{
+    drop_known_edges, verify_edges, EdgeHistoryConfig, EdgeTransition, Graph, GraphConfig,
+    GraphSnapshot, Inconsistency, Inner, LatencyStats, NextHopTable, PathLengthStats,
+    MAX_REACHABILITY_SCORE,
+};
 use crate::network_protocol::testonly as data;
-use crate::network_protocol::Edge;
 use crate::network_protocol::EDGE_MIN_TIMESTAMP_NONCE;
+use crate::network_protocol::{Edge, EdgeState};
+use crate::routing::bfs;
+use crate::stats::metrics;
 use crate::store;
 use crate::store::testonly::Component;
 use crate::testonly::make_rng;
@@ -9,8 +15,9 @@ use near_async::time;
 use near_crypto::SecretKey;
 use near_o11y::testonly::init_test_logger;
 use near_primitives::network::PeerId;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
 
 impl Graph {
     async fn simple_update(self: &Arc<Self>, clock: &time::Clock, edges: Vec<Edge>) {
@@ -35,6 +42,58 @@ impl Graph {
     }
 }
 
+impl Inner {
+    /// Constructs the graph synchronously out of `edges`, skipping the signature verification.
+    /// Pruning is disabled and the DB is never accessed.
+    fn from_edges(node_id: PeerId, edges: Vec<Edge>) -> Self {
+        let config = GraphConfig {
+            prune_unreachable_peers_after: time::Duration::MAX,
+            ..test_config(node_id.clone())
+        };
+        let mut inner = Inner {
+            graph: bfs::Graph::new(node_id),
+            config,
+            edges: Default::default(),
+            evictable: BTreeSet::new(),
+            peer_reachable_at: HashMap::new(),
+            reachability_scores: Arc::default(),
+            unreachable_peers_pruned_at: None,
+            edge_history: HashMap::new(),
+            recalc_latency: VecDeque::new(),
+            store: store(),
+        };
+        let now = time::FakeClock::default().now_utc();
+        for e in Edge::deduplicate(edges) {
+            inner.update_edge(now, e);
+        }
+        inner
+    }
+
+    /// Computes the next hops of the current graph, without updating any other state.
+    fn calculate_next_hops_sync(&self) -> NextHopTable {
+        self.graph.calculate_distance(&HashSet::new(), self.config.max_route_hops)
+    }
+}
+
+const SEC: time::Duration = time::Duration::seconds(1);
+
+/// Config of the graph used in tests: only the consistency self-check is enabled.
+/// Tests override the fields relevant to them.
+fn test_config(node_id: PeerId) -> GraphConfig {
+    GraphConfig {
+        node_id,
+        prune_unreachable_peers_after: 3 * SEC,
+        prune_interval: None,
+        max_route_hops: None,
+        reachability_recovery: None,
+        prune_edges_after: None,
+        max_edges: None,
+        edge_history: None,
+        verify_batch_size: 100,
+        self_check: true,
+    }
+}
+
 fn store() -> store::Store {
     store::Store::from(near_store::db::TestDB::new())
 }
@@ -49,17 +108,11 @@ async fn empty() {
     let mut rng = make_rng(87927345);
     let rng = &mut rng;
     let node_key = data::make_secret_key(rng);
-    let cfg = GraphConfig {
-        node_id: peer_id(&node_key),
-        prune_unreachable_peers_after: time::Duration::seconds(3),
-        prune_edges_after: None,
-    };
-    let g = Graph::new(cfg, store());
+    let cfg = test_config(peer_id(&node_key));
+    let g = Graph::new(cfg, store()).unwrap();
     g.check(&[], &[]).await;
 }
 
-const SEC: time::Duration = time::Duration::seconds(1);
-
 #[tokio::test]
 async fn one_edge() {
     init_test_logger();
@@ -67,12 +120,8 @@ async fn one_edge() {
     let mut rng = make_rng(87927345);
     let rng = &mut rng;
     let node_key = data::make_secret_key(rng);
-    let cfg = GraphConfig {
-        node_id: peer_id(&node_key),
-        prune_unreachable_peers_after: time::Duration::seconds(3),
-        prune_edges_after: None,
-    };
-    let g = Arc::new(Graph::new(cfg.clone(), store()));
+    let cfg = test_config(peer_id(&node_key));
+    let g = Arc::new(Graph::new(cfg.clone(), store()).unwrap());
 
     let p1 = data::make_secret_key(rng);
     let e1 = data::make_edge(&node_key, &p1, 1);
@@ -100,6 +149,73 @@ async fn one_edge() {
     g.check(&[], &[Component { edges: vec![e1v2.clone()], peers: vec![peer_id(&p1)] }]).await;
 }
 
+#[tokio::test]
+async fn prune_interval() {
+    init_test_logger();
+    let clock = time::FakeClock::default();
+    let mut rng = make_rng(87927345);
+    let rng = &mut rng;
+    let node_key = data::make_secret_key(rng);
+    let cfg = GraphConfig { prune_interval: Some(5 * SEC), ..test_config(peer_id(&node_key)) };
+    let g = Arc::new(Graph::new(cfg.clone(), store()).unwrap());
+
+    let p1 = data::make_secret_key(rng);
+    let e1 = data::make_edge(&node_key, &p1, 1);
+    let e1v2 = e1.remove_edge(peer_id(&p1), &p1);
+
+    tracing::info!(target:"test", "Connect and disconnect p1.");
+    g.simple_update(&clock.clock(), vec![e1.clone()]).await;
+    g.simple_update(&clock.clock(), vec![e1v2.clone()]).await;
+    g.check(&[e1v2.clone()], &[]).await;
+
+    tracing::info!(target:"test", "p1 is unreachable for long enough, but the prune interval hasn't passed.");
+    clock.advance(4 * SEC);
+    g.simple_update(&clock.clock(), vec![]).await;
+    g.check(&[e1v2.clone()], &[]).await;
+
+    tracing::info!(target:"test", "Once the prune interval passes, p1 is moved to DB.");
+    clock.advance(SEC);
+    g.simple_update(&clock.clock(), vec![]).await;
+    g.check(&[], &[Component { edges: vec![e1v2.clone()], peers: vec![peer_id(&p1)] }]).await;
+    // A non-positive interval is a config error.
+    let cfg = GraphConfig { prune_interval: Some(time::Duration::ZERO), ..cfg };
+    assert!(Graph::new(cfg, store()).is_err());
+}
+
+#[tokio::test]
+async fn peer_churn_metrics() {
+    init_test_logger();
+    let clock = time::FakeClock::default();
+    let mut rng = make_rng(87927345);
+    let rng = &mut rng;
+    let node_key = data::make_secret_key(rng);
+    let cfg = test_config(peer_id(&node_key));
+    let g = Arc::new(Graph::new(cfg.clone(), store()).unwrap());
+    let p1 = data::make_secret_key(rng);
+
+    // The metrics are global, so other tests may bump them concurrently.
+    let added = metrics::PEER_REACHABLE_ADDED.get();
+    let pruned = metrics::PEER_PRUNED.get();
+    let pushed = metrics::GRAPH_COMPONENTS.with_label_values(&["pushed"]).get();
+    let popped = metrics::GRAPH_COMPONENTS.with_label_values(&["popped"]).get();
+
+    const CYCLES: u64 = 3;
+    for i in 0..CYCLES {
+        tracing::info!(target:"test", "Connect, disconnect and prune p1.");
+        let e = data::make_edge(&node_key, &p1, 2 * i + 1);
+        g.simple_update(&clock.clock(), vec![e.clone()]).await;
+        g.simple_update(&clock.clock(), vec![e.remove_edge(peer_id(&p1), &p1)]).await;
+        clock.advance(4 * SEC);
+        g.simple_update(&clock.clock(), vec![]).await;
+    }
+
+    assert!(metrics::PEER_REACHABLE_ADDED.get() - added >= CYCLES);
+    assert!(metrics::PEER_PRUNED.get() - pruned >= CYCLES);
+    assert!(metrics::GRAPH_COMPONENTS.with_label_values(&["pushed"]).get() - pushed >= CYCLES);
+    // Every reconnection loads the component stored in the previous cycle.
+    assert!(metrics::GRAPH_COMPONENTS.with_label_values(&["popped"]).get() - popped >= CYCLES - 1);
+}
+
 #[tokio::test]
 async fn load_component() {
     init_test_logger();
@@ -107,12 +223,8 @@ async fn load_component() {
     let mut rng = make_rng(87927345);
     let rng = &mut rng;
     let node_key = data::make_secret_key(rng);
-    let cfg = GraphConfig {
-        node_id: peer_id(&node_key),
-        prune_unreachable_peers_after: time::Duration::seconds(3),
-        prune_edges_after: None,
-    };
-    let g = Arc::new(Graph::new(cfg.clone(), store()));
+    let cfg = test_config(peer_id(&node_key));
+    let g = Arc::new(Graph::new(cfg.clone(), store()).unwrap());
 
     let p1 = data::make_secret_key(rng);
     let p2 = data::make_secret_key(rng);
@@ -138,6 +250,70 @@ async fn load_component() {
     g.check(&[e1v2, e2, e3], &[]).await;
 }
 
+// Scenario from the Inner::load_component() docs, with node_id in place of D.
+#[tokio::test]
+async fn orphaned_components_are_deleted() {
+    init_test_logger();
+    let clock = time::FakeClock::default();
+    let mut rng = make_rng(87927345);
+    let rng = &mut rng;
+    let d = data::make_secret_key(rng);
+    let cfg = test_config(peer_id(&d));
+    let g = Arc::new(Graph::new(cfg.clone(), store()).unwrap());
+    let a = data::make_secret_key(rng);
+    let b = data::make_secret_key(rng);
+    let c = data::make_secret_key(rng);
+    let ab = data::make_edge(&a, &b, 1);
+    let ac = data::make_edge(&a, &c, 1);
+    let ad = data::make_edge(&a, &d, 1);
+    let bc = data::make_edge(&b, &c, 1);
+    let bd = data::make_edge(&b, &d, 1);
+    let cd = data::make_edge(&c, &d, 1);
+    g.simple_update(
+        &clock.clock(),
+        vec![ab.clone(), ac.clone(), ad.clone(), bc.clone(), bd.clone(), cd.clone()],
+    )
+    .await;
+
+    tracing::info!(target:"test", "A, B get removed and stored as C_1.");
+    let ac = ac.remove_edge(peer_id(&c), &c);
+    let ad = ad.remove_edge(peer_id(&d), &d);
+    let bc = bc.remove_edge(peer_id(&c), &c);
+    let bd = bd.remove_edge(peer_id(&d), &d);
+    g.simple_update(&clock.clock(), vec![ac.clone(), ad.clone(), bc.clone(), bd.clone()]).await;
+    clock.advance(4 * SEC);
+    g.simple_update(&clock.clock(), vec![]).await;
+
+    tracing::info!(target:"test", "C gets removed and stored as C_2.");
+    let cd = cd.remove_edge(peer_id(&d), &d);
+    g.simple_update(&clock.clock(), vec![cd.clone()]).await;
+    clock.advance(4 * SEC);
+    g.simple_update(&clock.clock(), vec![]).await;
+    g.check(
+        &[],
+        &[
+            Component {
+                edges: vec![ab.clone(), ac.clone(), ad.clone(), bc.clone(), bd.clone()],
+                peers: vec![peer_id(&a), peer_id(&b)],
+            },
+            Component { edges: vec![cd.clone()], peers: vec![peer_id(&c)] },
+        ],
+    )
+    .await;
+
+    tracing::info!(target:"test", "An active edge from D to A loads C_1.");
+    let ad = data::make_edge(&a, &d, ad.nonce() + 1);
+    g.simple_update(&clock.clock(), vec![ad.clone()]).await;
+
+    tracing::info!(target:"test", "C gets pruned again and stored as C_3, which orphans C_2.");
+    clock.advance(4 * SEC);
+    g.simple_update(&clock.clock(), vec![]).await;
+
+    tracing::info!(target:"test", "Orphaned C_2 is deleted during the next compaction.");
+    g.compact_components().await;
+    g.check(&[ab, ad, bd], &[Component { edges: vec![ac, bc], peers: vec![peer_id(&c)] }]).await;
+}
+
 #[tokio::test]
 async fn components_nonces_are_tracked_in_storage() {
     init_test_logger();
@@ -145,13 +321,9 @@ async fn components_nonces_are_tracked_in_storage() {
     let mut rng = make_rng(87927345);
     let rng = &mut rng;
     let node_key = data::make_secret_key(rng);
-    let cfg = GraphConfig {
-        node_id: peer_id(&node_key),
-        prune_unreachable_peers_after: time::Duration::seconds(3),
-        prune_edges_after: None,
-    };
+    let cfg = test_config(peer_id(&node_key));
     let store = store();
-    let g = Arc::new(Graph::new(cfg.clone(), store.clone()));
+    let g = Arc::new(Graph::new(cfg.clone(), store.clone()).unwrap());
 
     tracing::info!(target:"test", "Add an inactive edge and prune it.");
     let p1 = data::make_secret_key(rng);
@@ -178,7 +350,7 @@ async fn components_nonces_are_tracked_in_storage() {
     // overwritten, but rather a new one should be created.
     // This verifies that the last_component_nonce (which indicates which component IDs have been
     // already utilized) is persistently stored in DB.
-    let g = Arc::new(Graph::new(cfg.clone(), store));
+    let g = Arc::new(Graph::new(cfg.clone(), store).unwrap());
     let p4 = data::make_secret_key(rng);
     let e4 = data::make_edge_tombstone(&node_key, &p4);
     g.simple_update(&clock.clock(), vec![e4.clone()]).await;
@@ -225,11 +397,11 @@ async fn expired_edges() {
     let rng = &mut rng;
     let node_key = data::make_secret_key(rng);
     let cfg = GraphConfig {
-        node_id: peer_id(&node_key),
         prune_unreachable_peers_after: time::Duration::hours(100),
         prune_edges_after: Some(110 * SEC),
+        ..test_config(peer_id(&node_key))
     };
-    let g = Arc::new(Graph::new(cfg.clone(), store()));
+    let g = Arc::new(Graph::new(cfg.clone(), store()).unwrap());
 
     let p1 = data::make_secret_key(rng);
     let p2 = data::make_secret_key(rng);
@@ -279,3 +451,481 @@ async fn expired_edges() {
     g.simple_update(&clock.clock(), vec![]).await;
     g.check(&[], &[]).await;
 }
+
+#[tokio::test]
+async fn max_edges() {
+    init_test_logger();
+    let clock = time::FakeClock::default();
+    let mut rng = make_rng(87927345);
+    let rng = &mut rng;
+    let node_key = data::make_secret_key(rng);
+    let cfg = GraphConfig { max_edges: Some(3), ..test_config(peer_id(&node_key)) };
+    let g = Arc::new(Graph::new(cfg.clone(), store()).unwrap());
+
+    let p1 = data::make_secret_key(rng);
+    let p2 = data::make_secret_key(rng);
+    let p3 = data::make_secret_key(rng);
+    let p4 = data::make_secret_key(rng);
+    let e01 = data::make_edge(&node_key, &p1, 1);
+    let e02 = data::make_edge(&node_key, &p2, 1);
+    let e12 = data::make_edge(&p1, &p2, 1);
+    let e13 = data::make_edge(&p1, &p3, 3);
+    let e14 = data::make_edge(&p1, &p4, 1);
+    let e23 = data::make_edge(&p2, &p3, 5);
+
+    tracing::info!(target:"test", "Fill the graph up to the limit.");
+    g.simple_update(&clock.clock(), vec![e01.clone(), e12.clone(), e13.clone()]).await;
+    g.check(&[e01.clone(), e12.clone(), e13.clone()], &[]).await;
+
+    tracing::info!(target:"test", "A newer edge evicts the oldest non-local edge.");
+    g.simple_update(&clock.clock(), vec![e23.clone()]).await;
+    g.check(&[e01.clone(), e13.clone(), e23.clone()], &[]).await;
+
+    tracing::info!(target:"test", "An edge older than all the evictable ones is rejected.");
+    g.simple_update(&clock.clock(), vec![e14.clone()]).await;
+    g.check(&[e01.clone(), e13.clone(), e23.clone()], &[]).await;
+
+    tracing::info!(target:"test", "A local edge is always accepted and local edges are never evicted.");
+    g.simple_update(&clock.clock(), vec![e02.clone()]).await;
+    g.check(&[e01.clone(), e02.clone(), e23.clone()], &[]).await;
+}
+
+#[tokio::test]
+async fn max_edges_evicts_by_current_nonce() {
+    init_test_logger();
+    let clock = time::FakeClock::default();
+    let mut rng = make_rng(87927345);
+    let rng = &mut rng;
+    let node_key = data::make_secret_key(rng);
+    let cfg = GraphConfig { max_edges: Some(3), ..test_config(peer_id(&node_key)) };
+    let g = Arc::new(Graph::new(cfg.clone(), store()).unwrap());
+
+    let p1 = data::make_secret_key(rng);
+    let p2 = data::make_secret_key(rng);
+    let p3 = data::make_secret_key(rng);
+    let e01 = data::make_edge(&node_key, &p1, 1);
+    let e12 = data::make_edge(&p1, &p2, 1);
+    let e12v2 = data::make_edge(&p1, &p2, 7);
+    let e13 = data::make_edge(&p1, &p3, 3);
+    let e23 = data::make_edge(&p2, &p3, 5);
+
+    g.simple_update(&clock.clock(), vec![e01.clone(), e12.clone(), e13.clone()]).await;
+    tracing::info!(target:"test", "Update e12, so that e13 becomes the oldest evictable edge.");
+    g.simple_update(&clock.clock(), vec![e12v2.clone()]).await;
+    g.check(&[e01.clone(), e12v2.clone(), e13.clone()], &[]).await;
+
+    g.simple_update(&clock.clock(), vec![e23.clone()]).await;
+    g.check(&[e01.clone(), e12v2.clone(), e23.clone()], &[]).await;
+}
+
+#[tokio::test]
+async fn snapshot_counts() {
+    init_test_logger();
+    let clock = time::FakeClock::default();
+    let mut rng = make_rng(87927345);
+    let rng = &mut rng;
+    let node_key = data::make_secret_key(rng);
+    let cfg = test_config(peer_id(&node_key));
+    let g = Arc::new(Graph::new(cfg.clone(), store()).unwrap());
+    let snapshot = g.load();
+    assert_eq!(0, snapshot.total_edges);
+    assert_eq!(0, snapshot.active_edges);
+    assert_eq!(0, snapshot.reachable_peer_count);
+
+    // me - p1 x p2 - p3
+    //   \--------/
+    let p1 = data::make_secret_key(rng);
+    let p2 = data::make_secret_key(rng);
+    let p3 = data::make_secret_key(rng);
+    let edges = vec![
+        data::make_edge(&node_key, &p1, 1),
+        data::make_edge(&node_key, &p2, 1),
+        data::make_edge_tombstone(&p1, &p2),
+        data::make_edge(&p2, &p3, 1),
+    ];
+    g.simple_update(&clock.clock(), edges.clone()).await;
+    g.check(&edges, &[]).await;
+    let snapshot = g.load();
+    assert_eq!(4, snapshot.total_edges);
+    assert_eq!(3, snapshot.active_edges);
+    assert_eq!(3, snapshot.reachable_peer_count);
+    assert_eq!(snapshot.next_hops.len(), snapshot.reachable_peer_count);
+}
+
+#[tokio::test]
+async fn path_length_stats() {
+    init_test_logger();
+    let clock = time::FakeClock::default();
+    let mut rng = make_rng(87927345);
+    let rng = &mut rng;
+    let node_key = data::make_secret_key(rng);
+    let cfg = test_config(peer_id(&node_key));
+    let g = Arc::new(Graph::new(cfg.clone(), store()).unwrap());
+    assert_eq!(None, g.load().path_length_stats());
+
+    // me - p0 - p1 - ... - p9
+    let keys: Vec<_> = (0..10).map(|_| data::make_secret_key(rng)).collect();
+    let mut edges = vec![data::make_edge(&node_key, &keys[0], 1)];
+    for i in 1..keys.len() {
+        edges.push(data::make_edge(&keys[i - 1], &keys[i], 1));
+    }
+    g.simple_update(&clock.clock(), edges).await;
+    let snapshot = g.load();
+    assert_eq!(vec![0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1], snapshot.path_lengths);
+    assert_eq!(
+        Some(PathLengthStats { peers: 10, p50: 5, p99: 10, max: 10 }),
+        snapshot.path_length_stats()
+    );
+}
+
+#[tokio::test]
+async fn recalc_latency_stats() {
+    init_test_logger();
+    let clock = time::FakeClock::default();
+    let mut rng = make_rng(87927345);
+    let rng = &mut rng;
+    let node_key = data::make_secret_key(rng);
+    let cfg = test_config(peer_id(&node_key));
+    let g = Arc::new(Graph::new(cfg.clone(), store()).unwrap());
+    assert_eq!(None, g.recalc_latency_stats());
+
+    let mut edges = vec![];
+    for _ in 0..5 {
+        edges.push(data::make_edge(&node_key, &data::make_secret_key(rng), 1));
+        g.simple_update(&clock.clock(), edges.clone()).await;
+    }
+    // The latency is measured with the wall clock, so only the lower bounds are known.
+    let stats = g.recalc_latency_stats().unwrap();
+    assert_eq!(5, stats.samples);
+    assert!(time::Duration::ZERO < stats.p50);
+    assert!(stats.p50 <= stats.p99);
+    assert!(stats.p99 <= stats.max);
+
+    // Nearest-rank percentiles.
+    let samples: Vec<_> = (1..=200).rev().map(time::Duration::milliseconds).collect();
+    let want = LatencyStats {
+        samples: 200,
+        p50: time::Duration::milliseconds(100),
+        p99: time::Duration::milliseconds(198),
+        max: time::Duration::milliseconds(200),
+    };
+    assert_eq!(Some(want), LatencyStats::new(samples.iter()));
+}
+
+#[tokio::test]
+async fn reachability_score() {
+    init_test_logger();
+    let clock = time::FakeClock::default();
+    let mut rng = make_rng(87927345);
+    let rng = &mut rng;
+    let node_key = data::make_secret_key(rng);
+    let cfg = GraphConfig {
+        prune_unreachable_peers_after: 10 * SEC,
+        reachability_recovery: Some(MAX_REACHABILITY_SCORE / 4),
+        ..test_config(peer_id(&node_key))
+    };
+    let g = Arc::new(Graph::new(cfg.clone(), store()).unwrap());
+    let p1 = data::make_secret_key(rng);
+    let score = || g.load().reachability_scores.get(&peer_id(&p1)).copied();
+
+    tracing::info!(target:"test", "A newly discovered peer gets the max score.");
+    g.simple_update(&clock.clock(), vec![data::make_edge(&node_key, &p1, 1)]).await;
+    assert_eq!(Some(MAX_REACHABILITY_SCORE), score());
+
+    tracing::info!(target:"test", "Score of an unreachable peer decays over time.");
+    g.simple_update(&clock.clock(), vec![data::make_edge_tombstone(&node_key, &p1)]).await;
+    clock.advance(6 * SEC);
+    g.simple_update(&clock.clock(), vec![]).await;
+    assert_eq!(Some(MAX_REACHABILITY_SCORE * 4 / 10), score());
+
+    tracing::info!(target:"test", "Score of a reachable peer recovers gradually.");
+    g.simple_update(&clock.clock(), vec![data::make_edge(&node_key, &p1, 3)]).await;
+    assert_eq!(Some(MAX_REACHABILITY_SCORE * 4 / 10 + MAX_REACHABILITY_SCORE / 4), score());
+    clock.advance(SEC);
+    g.simple_update(&clock.clock(), vec![]).await;
+    g.simple_update(&clock.clock(), vec![]).await;
+    assert_eq!(Some(MAX_REACHABILITY_SCORE), score());
+
+    tracing::info!(target:"test", "Peer is pruned once its score hits 0.");
+    let tombstone = data::make_edge(&node_key, &p1, 3).remove_edge(peer_id(&node_key), &node_key);
+    g.simple_update(&clock.clock(), vec![tombstone]).await;
+    clock.advance(10 * SEC);
+    g.simple_update(&clock.clock(), vec![]).await;
+    assert_eq!(Some(0), score());
+    clock.advance(SEC);
+    g.simple_update(&clock.clock(), vec![]).await;
+    assert_eq!(None, score());
+    assert!(g.load().edges.is_empty());
+}
+
+#[tokio::test]
+async fn snapshot_diff() {
+    init_test_logger();
+    let clock = time::FakeClock::default();
+    let mut rng = make_rng(87927345);
+    let rng = &mut rng;
+    let node_key = data::make_secret_key(rng);
+    let cfg = test_config(peer_id(&node_key));
+    let g = Arc::new(Graph::new(cfg.clone(), store()).unwrap());
+    let p1 = data::make_secret_key(rng);
+    let p2 = data::make_secret_key(rng);
+    let p3 = data::make_secret_key(rng);
+
+    // me - p1 - p2
+    let e01 = data::make_edge(&node_key, &p1, 1);
+    let e12 = data::make_edge(&p1, &p2, 1);
+    g.simple_update(&clock.clock(), vec![e01.clone(), e12.clone()]).await;
+    let prev = g.load();
+    assert!(prev.diff(&prev).is_empty());
+
+    // me - p1 x p2
+    //  \-- p3
+    let e03 = data::make_edge(&node_key, &p3, 1);
+    let e12_tombstone = data::make_edge_tombstone(&p1, &p2);
+    g.simple_update(&clock.clock(), vec![e03.clone(), e12_tombstone.clone()]).await;
+    let next = g.load();
+
+    let diff = next.diff(&prev);
+    assert_eq!(as_set(&[e03.clone(), e12_tombstone.clone()]), as_set(&diff.added_edges));
+    assert!(diff.removed_edges.is_empty());
+    assert_eq!(
+        HashSet::from([peer_id(&p2), peer_id(&p3)]),
+        diff.changed_next_hops.into_iter().collect()
+    );
+
+    // Reversed diff reports the edges which are gone.
+    let diff = prev.diff(&next);
+    assert_eq!(as_set(&[e12]), as_set(&diff.added_edges));
+    assert_eq!(as_set(&[e03]), as_set(&diff.removed_edges));
+}
+
+#[tokio::test]
+async fn self_check() {
+    init_test_logger();
+    let clock = time::FakeClock::default();
+    let mut rng = make_rng(87927345);
+    let rng = &mut rng;
+    let node_key = data::make_secret_key(rng);
+    let cfg = test_config(peer_id(&node_key));
+    let g = Arc::new(Graph::new(cfg.clone(), store()).unwrap());
+    let p1 = data::make_secret_key(rng);
+    let p2 = data::make_secret_key(rng);
+
+    // me - p1 - p2
+    let e01 = data::make_edge(&node_key, &p1, 1);
+    let e12 = data::make_edge(&p1, &p2, 1);
+    g.simple_update(&clock.clock(), vec![e01.clone(), e12.clone()]).await;
+    assert_eq!(Vec::<Inconsistency>::new(), g.self_check());
+
+    // Drop the local edge, without recomputing the routing table.
+    g.inner.lock().edges.remove(e01.key());
+    let got: HashSet<_> = g.self_check().into_iter().collect();
+    let want = HashSet::from([
+        Inconsistency::NextHopNotLocal { peer_id: peer_id(&p1), next_hop: peer_id(&p1) },
+        Inconsistency::NextHopNotLocal { peer_id: peer_id(&p2), next_hop: peer_id(&p1) },
+    ]);
+    assert_eq!(want, got);
+}
+
+#[test]
+fn unreliable_peers() {
+    let mut rng = make_rng(87927345);
+    let rng = &mut rng;
+    let node_key = data::make_secret_key(rng);
+    let cfg = test_config(peer_id(&node_key));
+    let g = Graph::new(cfg, store()).unwrap();
+    let p1 = peer_id(&data::make_secret_key(rng));
+    let p2 = peer_id(&data::make_secret_key(rng));
+    assert!(g.unreliable_peers().is_empty());
+    assert!(!g.is_unreliable(&p1));
+
+    g.set_unreliable_peers(HashSet::from([p1.clone()]));
+    assert_eq!(HashSet::from([p1.clone()]), *g.unreliable_peers());
+    assert!(g.is_unreliable(&p1));
+    assert!(!g.is_unreliable(&p2));
+
+    g.set_unreliable_peers(HashSet::new());
+    assert!(!g.is_unreliable(&p1));
+}
+
+#[tokio::test]
+async fn paths_to() {
+    init_test_logger();
+    let clock = time::FakeClock::default();
+    let mut rng = make_rng(87927345);
+    let rng = &mut rng;
+    let node_key = data::make_secret_key(rng);
+    let cfg = test_config(peer_id(&node_key));
+    let g = Arc::new(Graph::new(cfg.clone(), store()).unwrap());
+    let p1 = data::make_secret_key(rng);
+    let p2 = data::make_secret_key(rng);
+    let p3 = data::make_secret_key(rng);
+
+    // me - p1 - p3
+    //  \-- p2 --/
+    let e01 = data::make_edge(&node_key, &p1, 1);
+    let e02 = data::make_edge(&node_key, &p2, 1);
+    let e13 = data::make_edge(&p1, &p3, 1);
+    let e23 = data::make_edge(&p2, &p3, 1);
+    g.simple_update(&clock.clock(), vec![e01, e02, e13.clone(), e23]).await;
+    let got: HashSet<_> = g.paths_to(&peer_id(&p3), 5).into_iter().collect();
+    let want = HashSet::from([vec![peer_id(&p1), peer_id(&p3)], vec![peer_id(&p2), peer_id(&p3)]]);
+    assert_eq!(want, got);
+
+    // Removing p1 - p3 leaves a single path.
+    g.simple_update(&clock.clock(), vec![e13.remove_edge(peer_id(&p1), &p1)]).await;
+    assert_eq!(vec![vec![peer_id(&p2), peer_id(&p3)]], g.paths_to(&peer_id(&p3), 5));
+}
+
+#[tokio::test]
+async fn edge_history() {
+    init_test_logger();
+    let clock = time::FakeClock::default();
+    let mut rng = make_rng(87927345);
+    let rng = &mut rng;
+    let node_key = data::make_secret_key(rng);
+    let cfg = GraphConfig {
+        edge_history: Some(EdgeHistoryConfig { len: 3, all_edges: false }),
+        ..test_config(peer_id(&node_key))
+    };
+    let g = Arc::new(Graph::new(cfg.clone(), store()).unwrap());
+
+    let p1 = data::make_secret_key(rng);
+    let p2 = data::make_secret_key(rng);
+    let e1 = data::make_edge(&node_key, &p1, 1);
+    let e12 = data::make_edge(&p1, &p2, 1);
+
+    tracing::info!(target:"test", "Toggle the local edge a few times.");
+    let mut want = vec![];
+    for nonce in [1, 3] {
+        let active = data::make_edge(&node_key, &p1, nonce);
+        let removed = active.remove_edge(peer_id(&p1), &p1);
+        for e in [active, removed] {
+            g.simple_update(&clock.clock(), vec![e.clone(), e12.clone()]).await;
+            want.push(EdgeTransition {
+                nonce: e.nonce(),
+                state: e.edge_type(),
+                at: clock.now_utc(),
+            });
+            clock.advance(SEC / 2);
+        }
+    }
+
+    tracing::info!(target:"test", "Only the most recent transitions are kept.");
+    let got = g.edge_history(e1.key());
+    assert_eq!(&want[1..], &got[..]);
+    assert_eq!(
+        vec![EdgeState::Removed, EdgeState::Active, EdgeState::Removed],
+        got.iter().map(|t| t.state.clone()).collect::<Vec<_>>()
+    );
+
+    tracing::info!(target:"test", "Non-local edges are not tracked.");
+    assert_eq!(Vec::<EdgeTransition>::new(), g.edge_history(e12.key()));
+}
+
+#[test]
+fn next_hops_chain() {
+    let mut rng = make_rng(87927345);
+    let rng = &mut rng;
+    let keys: Vec<_> = (0..4).map(|_| data::make_secret_key(rng)).collect();
+    let ids: Vec<_> = keys.iter().map(peer_id).collect();
+    // 0 - 1 - 2 - 3
+    let edges = (1..keys.len()).map(|i| data::make_edge(&keys[i - 1], &keys[i], 1)).collect();
+
+    let inner = Inner::from_edges(ids[0].clone(), edges);
+    let want: NextHopTable = ids[1..].iter().map(|id| (id.clone(), vec![ids[1].clone()])).collect();
+    assert_eq!(want, inner.calculate_next_hops_sync());
+
+    // From the middle of the chain, the peers are reachable in both directions.
+    let edges = (1..keys.len()).map(|i| data::make_edge(&keys[i - 1], &keys[i], 1)).collect();
+    let inner = Inner::from_edges(ids[2].clone(), edges);
+    let want: NextHopTable = [
+        (ids[0].clone(), vec![ids[1].clone()]),
+        (ids[1].clone(), vec![ids[1].clone()]),
+        (ids[3].clone(), vec![ids[3].clone()]),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(want, inner.calculate_next_hops_sync());
+}
+
+/// Constructs an edge with an invalid signature of one of the peers.
+fn make_invalid_edge(a: &SecretKey, b: &SecretKey, nonce: u64) -> Edge {
+    let (p0, p1) = data::make_edge(a, b, nonce).key().clone();
+    let hash = Edge::build_hash(&p0, &p1, nonce);
+    Edge::new(p0, p1, nonce, a.sign(hash.as_ref()), a.sign(hash.as_ref()))
+}
+
+#[test]
+fn verify_edges_in_batches() {
+    init_test_logger();
+    let mut rng = make_rng(87927345);
+    let rng = &mut rng;
+    let keys: Vec<_> = (0..6).map(|_| data::make_secret_key(rng)).collect();
+    let valid: Vec<_> = (1..keys.len()).map(|i| data::make_edge(&keys[0], &keys[i], 1)).collect();
+    let invalid = make_invalid_edge(&keys[1], &keys[2], 1);
+    assert!(!invalid.verify());
+
+    tracing::info!(target:"test", "All valid edges are returned.");
+    let canceled = CancellationToken::new();
+    let (got, ok) = verify_edges(valid.clone(), 2, &canceled);
+    assert!(ok);
+    assert_eq!(as_set(&valid), as_set(&got));
+
+    tracing::info!(target:"test", "Invalid edge is filtered out, edges from earlier batches are returned.");
+    let mut edges = valid.clone();
+    edges.push(invalid.clone());
+    let (got, ok) = verify_edges(edges, 2, &canceled);
+    assert!(!ok);
+    let got = as_set(&got);
+    assert!(!got.contains(&invalid));
+    assert!(got.is_subset(&as_set(&valid)));
+    assert!(as_set(&valid[..4]).is_subset(&got));
+
+    tracing::info!(target:"test", "Nothing is verified once canceled.");
+    canceled.cancel();
+    assert_eq!((vec![], true), verify_edges(valid, 2, &canceled));
+}
+
+#[test]
+fn drop_known_edges_from_big_batch() {
+    init_test_logger();
+    let mut rng = make_rng(87927345);
+    let rng = &mut rng;
+    let keys: Vec<_> = (0..100).map(|_| data::make_secret_key(rng)).collect();
+    let node_id = peer_id(&keys[0]);
+    let mut known = vec![];
+    for i in 0..keys.len() {
+        for j in i + 1..keys.len() {
+            known.push(data::make_edge(&keys[i], &keys[j], 2));
+        }
+    }
+    let inner = Inner::from_edges(node_id, known.clone());
+    let snapshot = GraphSnapshot { edges: inner.edges.clone(), ..GraphSnapshot::default() };
+
+    // The batch consists mostly of the known edges and their older versions,
+    // with a few duplicated new ones.
+    let new: Vec<_> =
+        (1..keys.len()).step_by(20).map(|i| data::make_edge(&keys[0], &keys[i], 3)).collect();
+    let mut batch = known.clone();
+    for i in 0..keys.len() {
+        for j in i + 1..keys.len() {
+            batch.push(data::make_edge(&keys[i], &keys[j], 1));
+        }
+    }
+    batch.extend(new.iter().cloned());
+    batch.extend(new.iter().cloned());
+    tracing::info!(target:"test", "batch size = {}", batch.len());
+
+    let got = drop_known_edges(&snapshot, batch);
+    assert_eq!(as_set(&new), as_set(&got));
+    assert_eq!(new.len(), got.len());
+    // Every returned edge is newer than the one in the snapshot.
+    for e in &got {
+        assert!(e.nonce() > snapshot.edges.get(e.key()).unwrap().nonce());
+    }
+}
+
+fn as_set(edges: &[Edge]) -> HashSet<Edge> {
+    edges.iter().cloned().collect()
+}
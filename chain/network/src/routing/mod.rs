@@ -1,7 +1,11 @@
 mod bfs;
 pub(crate) mod edge;
 mod graph;
+pub(crate) mod retry_buffer;
 pub(crate) mod route_back_cache;
 pub mod routing_table_view;
+pub(crate) mod rtt;
 
-pub(crate) use graph::{Graph, GraphConfig, NextHopTable};
+pub(crate) use graph::{
+    EdgeHistoryConfig, Graph, GraphConfig, NextHopTable, MAX_REACHABILITY_SCORE,
+};
@@ -0,0 +1,127 @@
+use crate::network_protocol::RoutedMessageBody;
+use crate::stats::metrics;
+use near_async::time;
+use near_primitives::types::AccountId;
+use std::collections::VecDeque;
+
+/// Message which couldn't be routed to its target account yet.
+pub(crate) struct PendingMessage {
+    /// Once this deadline passes, the message is dropped.
+    pub deadline: time::Instant,
+    pub account_id: AccountId,
+    pub body: RoutedMessageBody,
+}
+
+/// Bounded buffer of important messages, for which no route was known at the time of sending.
+///
+/// Routing gaps are usually transient (an AnnounceAccount or an edge is about to arrive),
+/// so instead of dropping an important message right away we keep it for a short window
+/// and let the caller re-attempt routing periodically.
+/// Once the buffer is full, the oldest message is dropped to make room for the new one.
+pub(crate) struct RetryBuffer {
+    /// Maximal number of messages kept in the buffer.
+    capacity: usize,
+    /// How long a message is kept in the buffer before being dropped.
+    window: time::Duration,
+    messages: VecDeque<PendingMessage>,
+}
+
+impl RetryBuffer {
+    pub fn new(capacity: usize, window: time::Duration) -> Self {
+        Self { capacity, window, messages: VecDeque::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Buffers a message for the retry window, starting at `now`.
+    pub fn push(&mut self, now: time::Instant, account_id: AccountId, body: RoutedMessageBody) {
+        self.push_pending(PendingMessage { deadline: now + self.window, account_id, body });
+    }
+
+    /// Buffers back a message which has been taken out with `take`, keeping its deadline.
+    pub fn push_pending(&mut self, msg: PendingMessage) {
+        if self.capacity == 0 {
+            metrics::MessageDropped::RetryBufferFull.inc(&msg.body);
+            return;
+        }
+        while self.messages.len() >= self.capacity {
+            if let Some(dropped) = self.messages.pop_front() {
+                metrics::MessageDropped::RetryBufferFull.inc(&dropped.body);
+            }
+        }
+        self.messages.push_back(msg);
+    }
+
+    /// Takes all the buffered messages, dropping the ones whose retry window has expired.
+    pub fn take(&mut self, now: time::Instant) -> Vec<PendingMessage> {
+        let mut pending = vec![];
+        for msg in std::mem::take(&mut self.messages) {
+            if msg.deadline <= now {
+                tracing::debug!(target: "network", to = ?msg.account_id, msg = ?msg.body, "Drop message: retry window expired");
+                metrics::MessageDropped::RetryWindowExpired.inc(&msg.body);
+                continue;
+            }
+            pending.push(msg);
+        }
+        pending
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::network_protocol::testonly as data;
+    use crate::network_protocol::Ping;
+    use crate::testonly::make_rng;
+
+    fn make_ping(nonce: u64) -> RoutedMessageBody {
+        let mut rng = make_rng(921853233);
+        RoutedMessageBody::Ping(Ping { nonce, source: data::make_peer_id(&mut rng) })
+    }
+
+    fn nonces(msgs: &[PendingMessage]) -> Vec<u64> {
+        msgs.iter()
+            .map(|msg| match &msg.body {
+                RoutedMessageBody::Ping(ping) => ping.nonce,
+                _ => panic!("unexpected message"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn expired() {
+        let clock = time::FakeClock::default();
+        let mut rng = make_rng(921853233);
+        let account_id = data::make_account_id(&mut rng);
+        let mut buffer = RetryBuffer::new(10, time::Duration::seconds(5));
+        buffer.push(clock.now(), account_id.clone(), make_ping(0));
+        clock.advance(time::Duration::seconds(3));
+        buffer.push(clock.now(), account_id, make_ping(1));
+
+        let pending = buffer.take(clock.now());
+        assert_eq!(vec![0, 1], nonces(&pending));
+        assert_eq!(0, buffer.len());
+        for msg in pending {
+            buffer.push_pending(msg);
+        }
+
+        // The first message outlives its window, while the second is still kept.
+        clock.advance(time::Duration::seconds(3));
+        assert_eq!(vec![1], nonces(&buffer.take(clock.now())));
+    }
+
+    #[test]
+    fn capacity() {
+        let clock = time::FakeClock::default();
+        let mut rng = make_rng(921853233);
+        let account_id = data::make_account_id(&mut rng);
+        let mut buffer = RetryBuffer::new(2, time::Duration::seconds(5));
+        for nonce in 0..4 {
+            buffer.push(clock.now(), account_id.clone(), make_ping(nonce));
+        }
+        assert_eq!(2, buffer.len());
+        assert_eq!(vec![2, 3], nonces(&buffer.take(clock.now())));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::stats::metrics;
 use near_async::time;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::PeerId;
@@ -8,7 +9,7 @@ const DEFAULT_CAPACITY: usize = 100_000;
 /// default value for `evict_timeout`
 const DEFAULT_CACHE_EVICT_TIMEOUT: time::Duration = time::Duration::milliseconds(120_000);
 /// default value for `remove_frequent_min_size`
-const DEFAULT_REMOVE_BATCH_SIZE: usize = 100;
+pub(crate) const DEFAULT_REMOVE_BATCH_SIZE: usize = 100;
 
 /// Cache to store route back messages.
 ///
@@ -42,6 +43,7 @@ const DEFAULT_REMOVE_BATCH_SIZE: usize = 100;
 /// Rationale:
 ///
 /// - Old entries in the cache will be eventually removed (no memory leak).
+/// - Entries older than `evict_timeout` are never returned by `remove`.
 /// - If the cache is not at full capacity, all new records will be stored.
 /// - If a peer try to abuse the system, it will be able to allocate at most
 ///     `capacity / number_of_active_connections` entries.
@@ -128,6 +130,8 @@ impl RouteBackCache {
             }
         }
 
+        metrics::ROUTE_BACK_CACHE_EVICTED.inc_by(removed as u64);
+        metrics::ROUTE_BACK_CACHE_SIZE.sub(removed as i64);
         self.size_per_target.remove(&(size, target.clone()));
         // Since self.size is equal to capacity - real_size, adding 1, is equivalent to subtracting 1 from the real size.
         size += removed;
@@ -155,6 +159,8 @@ impl RouteBackCache {
                 for evicted in value.iter() {
                     self.main.remove(&evicted.1);
                 }
+                metrics::ROUTE_BACK_CACHE_EVICTED.inc_by(value.len() as u64);
+                metrics::ROUTE_BACK_CACHE_SIZE.sub(value.len() as i64);
 
                 *value = keep;
                 let new_size = value.len();
@@ -182,10 +188,13 @@ impl RouteBackCache {
         self.main.get(hash).map(|(_, target)| target)
     }
 
+    /// Removes the entry for `hash` and returns its target.
+    /// Returns None if the entry is missing or has expired.
     pub fn remove(&mut self, clock: &time::Clock, hash: &CryptoHash) -> Option<PeerId> {
         self.remove_evicted(clock);
 
         if let Some((time, target)) = self.main.remove(hash) {
+            metrics::ROUTE_BACK_CACHE_SIZE.dec();
             // Number of elements associated with this target
             let mut size = self.record_per_target.get(&target).map(|x| x.len()).unwrap();
 
@@ -208,6 +217,11 @@ impl RouteBackCache {
                 self.size_per_target.insert((self.capacity - size, target.clone()));
             }
 
+            // Expired entries are removed lazily.
+            if clock.now() - time > self.evict_timeout {
+                metrics::ROUTE_BACK_CACHE_EVICTED.inc();
+                return None;
+            }
             Some(target)
         } else {
             None
@@ -224,6 +238,7 @@ impl RouteBackCache {
         let now = clock.now();
 
         self.main.insert(hash, (now, target.clone()));
+        metrics::ROUTE_BACK_CACHE_SIZE.inc();
 
         let mut size = self.record_per_target.get(&target).map_or(0, |x| x.len());
 
@@ -238,6 +253,12 @@ impl RouteBackCache {
     }
 }
 
+impl Drop for RouteBackCache {
+    fn drop(&mut self) {
+        metrics::ROUTE_BACK_CACHE_SIZE.sub(self.main.len() as i64);
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -458,4 +479,43 @@ mod test {
             }
         }
     }
+
+    /// Insert past the capacity. Check that the oldest entries are gone, while the recent ones remain.
+    #[test]
+    fn insert_past_capacity() {
+        let clock = time::FakeClock::default();
+        let mut cache = RouteBackCache::new(4, time::Duration::milliseconds(1000000000), 1);
+        let peer = PeerId::random();
+        let hashes: Vec<_> = (0..6).map(|i| hash(&[i])).collect();
+
+        for h in &hashes {
+            cache.insert(&clock.clock(), *h, peer.clone());
+            clock.advance(time::Duration::milliseconds(1));
+            check_consistency(&cache);
+        }
+
+        assert_eq!(cache.get(&hashes[0]), None);
+        assert_eq!(cache.get(&hashes[1]), None);
+        for h in &hashes[2..] {
+            assert_eq!(cache.get(h), Some(&peer));
+        }
+    }
+
+    /// Check that an expired entry is not returned by remove, even if the cache is not full.
+    #[test]
+    fn remove_expired() {
+        let clock = time::FakeClock::default();
+        let mut cache = RouteBackCache::new(100, time::Duration::milliseconds(10), 1);
+        let (peer0, hash0) = create_message(0);
+        let (peer1, hash1) = create_message(1);
+
+        cache.insert(&clock.clock(), hash0, peer0);
+        clock.advance(time::Duration::milliseconds(20));
+        cache.insert(&clock.clock(), hash1, peer1.clone());
+        assert_eq!(cache.remove(&clock.clock(), &hash0), None);
+        check_consistency(&cache);
+        assert_eq!(cache.remove(&clock.clock(), &hash1), Some(peer1));
+        check_consistency(&cache);
+        assert_eq!(cache.main.len(), 0);
+    }
 }
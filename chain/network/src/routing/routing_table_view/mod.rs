@@ -1,14 +1,17 @@
 use crate::network_protocol::PeerIdOrHash;
 use crate::routing;
 use crate::routing::route_back_cache::RouteBackCache;
+use crate::stats::metrics;
 use crate::store;
+use arc_swap::ArcSwap;
 use lru::LruCache;
 use near_async::time;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
-use near_primitives::types::AccountId;
+use near_primitives::types::{AccountId, EpochId};
 use parking_lot::Mutex;
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 #[cfg(test)]
@@ -17,21 +20,59 @@ mod tests;
 const ANNOUNCE_ACCOUNT_CACHE_SIZE: usize = 10_000;
 const LAST_ROUTED_CACHE_SIZE: usize = 10_000;
 
-pub(crate) struct RoutingTableView(Mutex<Inner>);
+/// AnnounceAccounts which haven't been refreshed for that long are dropped,
+/// unless they are for one of the epochs of the chain head, see `Inner::live_epochs`.
+pub(crate) const ANNOUNCE_ACCOUNT_TTL: time::Duration = time::Duration::hours(1);
+/// How often a node rebroadcasts its own AnnounceAccounts, to keep them from expiring.
+pub(crate) const ANNOUNCE_ACCOUNT_REFRESH_INTERVAL: time::Duration = time::Duration::minutes(20);
+/// Minimal age of an AnnounceAccount, at which a rebroadcast of the same announcement
+/// is accepted as a refresh. Copies received before that are treated as duplicates.
+const ANNOUNCE_ACCOUNT_REFRESH_MIN_AGE: time::Duration = time::Duration::minutes(10);
+
+pub(crate) struct RoutingTableView {
+    inner: Mutex<Inner>,
+    /// Routing state as of the last update, see `RoutingTableView::snapshot`.
+    snapshot: ArcSwap<RoutingSnapshot>,
+}
+
+/// Point-in-time view of the routing state.
+#[derive(Clone, Debug, Default)]
+pub struct RoutingSnapshot {
+    /// Peers reachable from this node.
+    pub reachable_peers: Arc<HashSet<PeerId>>,
+    /// Owners of the accounts kept in the announce accounts cache.
+    pub account_owners: im::HashMap<AccountId, PeerId>,
+    pub next_hops: Arc<routing::NextHopTable>,
+}
 
 struct Inner {
     /// Maps an account_id to a peer owning it.
     account_peers: LruCache<AccountId, AnnounceAccount>,
+    /// Mirror of account_peers, mapping an account_id to its owner.
+    /// It is a persistent map, so that the snapshots can be cloned cheaply.
+    account_owners: im::HashMap<AccountId, PeerId>,
+    /// Reverse index of account_owners, mapping a peer to the accounts it owns.
+    peer_accounts: HashMap<PeerId, HashSet<AccountId>>,
     /// Subset of account_peers, which we have broadcasted to the peers.
     /// It is used to skip rebroadcasting the same data multiple times.
     /// It contains less entries than account_peers in case some AnnounceAccounts
     /// have been loaded from storage without broadcasting.
     account_peers_broadcasted: LruCache<AccountId, AnnounceAccount>,
+    /// Time at which each account in account_peers has been added or last refreshed.
+    /// It is persisted together with the AnnounceAccount.
+    account_refreshed_at: HashMap<AccountId, time::Utc>,
+    /// Current and next epoch of the chain head. AnnounceAccounts for these epochs
+    /// don't expire: nodes running older binaries announce their accounts just once
+    /// per epoch and don't propagate the refreshes within an epoch.
+    live_epochs: HashSet<EpochId>,
 
     /// For each peer, the set of neighbors which are one hop closer to `my_peer_id`.
     /// Alternatively, if we look at the set of all shortest path from `my_peer_id` to peer,
     /// this will be the set of first nodes on all such paths.
     next_hops: Arc<routing::NextHopTable>,
+    /// Reachability scores of the peers. Next hops with higher scores are preferred.
+    /// Peers without a score are treated as if they had the max score.
+    reachability_scores: Arc<HashMap<PeerId, u32>>,
     /// Hash of messages that requires routing back to respective previous hop.
     route_back: RouteBackCache,
     /// Access to store on disk
@@ -45,12 +86,19 @@ struct Inner {
 
 impl Inner {
     /// Select a connected peer on some shortest path to `peer_id`.
-    /// If there are several such peers, pick the least recently used one.
+    /// If there are several such peers, pick the least recently used one among
+    /// those with the highest reachability score.
     fn find_route_from_peer_id(&mut self, peer_id: &PeerId) -> Result<PeerId, FindRouteError> {
         let peers = self.next_hops.get(peer_id).ok_or(FindRouteError::PeerUnreachable)?;
         let next_hop = peers
             .iter()
-            .min_by_key(|p| self.last_routed.get(*p).copied().unwrap_or(0))
+            .min_by_key(|p| {
+                let score = self.reachability_scores.get(*p).copied();
+                (
+                    Reverse(score.unwrap_or(routing::MAX_REACHABILITY_SCORE)),
+                    self.last_routed.get(*p).copied().unwrap_or(0),
+                )
+            })
             .ok_or(FindRouteError::PeerUnreachable)?;
         self.last_routed.put(next_hop.clone(), self.find_route_calls);
         self.find_route_calls += 1;
@@ -62,7 +110,61 @@ impl Inner {
         self.route_back.remove(clock, &hash)
     }
 
+    /// Inserts AnnounceAccount into account_peers, keeping account_owners in sync.
+    fn put_announce(&mut self, refreshed_at: time::Utc, aa: AnnounceAccount) {
+        if !self.account_peers.contains(&aa.account_id)
+            && self.account_peers.len() == self.account_peers.cap()
+        {
+            // The least recently used entry is about to be evicted.
+            if let Some((evicted, _)) = self.account_peers.peek_lru() {
+                if let Some(owner) = self.account_owners.remove(evicted) {
+                    remove_peer_account(&mut self.peer_accounts, &owner, evicted);
+                }
+                self.account_refreshed_at.remove(evicted);
+            }
+        }
+        if let Some(old) = self.account_owners.insert(aa.account_id.clone(), aa.peer_id.clone()) {
+            remove_peer_account(&mut self.peer_accounts, &old, &aa.account_id);
+        }
+        self.peer_accounts.entry(aa.peer_id.clone()).or_default().insert(aa.account_id.clone());
+        self.account_refreshed_at.insert(aa.account_id.clone(), refreshed_at);
+        self.account_peers.put(aa.account_id.clone(), aa);
+        metrics::ANNOUNCE_ACCOUNTS.set(self.account_peers.len() as i64);
+    }
+
+    /// Time elapsed since the AnnounceAccount for the given AccountId has been refreshed.
+    fn account_age(&self, now: time::Utc, account_id: &AccountId) -> Option<time::Duration> {
+        self.account_refreshed_at.get(account_id).map(|t| now - *t)
+    }
+
+    fn is_expired(&self, now: time::Utc, account_id: &AccountId) -> bool {
+        let Some(aa) = self.account_peers.peek(account_id) else { return false };
+        !self.live_epochs.contains(&aa.epoch_id)
+            && self.account_age(now, account_id).map_or(false, |age| age >= ANNOUNCE_ACCOUNT_TTL)
+    }
+
+    fn is_refresh_due(&self, now: time::Utc, account_id: &AccountId) -> bool {
+        self.account_age(now, account_id)
+            .map_or(true, |age| age >= ANNOUNCE_ACCOUNT_REFRESH_MIN_AGE)
+    }
+
+    /// Removes an expired AnnounceAccount from memory.
+    /// The caller is responsible for removing it from store as well.
+    fn remove_expired(&mut self, account_id: &AccountId) {
+        self.account_peers.pop(account_id);
+        if let Some(owner) = self.account_owners.remove(account_id) {
+            remove_peer_account(&mut self.peer_accounts, &owner, account_id);
+        }
+        self.account_peers_broadcasted.pop(account_id);
+        self.account_refreshed_at.remove(account_id);
+        metrics::ANNOUNCE_ACCOUNTS.set(self.account_peers.len() as i64);
+        metrics::ANNOUNCE_ACCOUNTS_EXPIRED.inc();
+    }
+
     /// Get AnnounceAccount for the given AccountId.
+    /// AnnounceAccounts loaded from store keep their persisted refresh time,
+    /// so that the stale ones are not revived. The ones persisted without
+    /// the refresh time are treated as refreshed long ago.
     fn get_announce(&mut self, account_id: &AccountId) -> Option<AnnounceAccount> {
         if let Some(aa) = self.account_peers.get(account_id) {
             return Some(aa.clone());
@@ -73,14 +175,28 @@ impl Inner {
                 None
             }
             Ok(None) => None,
-            Ok(Some(a)) => {
-                self.account_peers.put(account_id.clone(), a.clone());
+            Ok(Some((a, refreshed_at))) => {
+                self.put_announce(refreshed_at.unwrap_or(time::Utc::UNIX_EPOCH), a.clone());
                 Some(a)
             }
         }
     }
 }
 
+/// Removes account_id from the accounts owned by peer_id in the peer_accounts index.
+fn remove_peer_account(
+    peer_accounts: &mut HashMap<PeerId, HashSet<AccountId>>,
+    peer_id: &PeerId,
+    account_id: &AccountId,
+) {
+    if let Some(accounts) = peer_accounts.get_mut(peer_id) {
+        accounts.remove(account_id);
+        if accounts.is_empty() {
+            peer_accounts.remove(peer_id);
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) enum FindRouteError {
     PeerUnreachable,
@@ -89,26 +205,65 @@ pub(crate) enum FindRouteError {
 
 impl RoutingTableView {
     pub fn new(store: store::Store) -> Self {
-        Self(Mutex::new(Inner {
-            account_peers: LruCache::new(ANNOUNCE_ACCOUNT_CACHE_SIZE),
-            account_peers_broadcasted: LruCache::new(ANNOUNCE_ACCOUNT_CACHE_SIZE),
-            next_hops: Default::default(),
-            route_back: RouteBackCache::default(),
-            store,
-            find_route_calls: 0,
-            last_routed: LruCache::new(LAST_ROUTED_CACHE_SIZE),
-        }))
+        Self {
+            inner: Mutex::new(Inner {
+                account_peers: LruCache::new(ANNOUNCE_ACCOUNT_CACHE_SIZE),
+                account_owners: im::HashMap::new(),
+                peer_accounts: HashMap::new(),
+                account_peers_broadcasted: LruCache::new(ANNOUNCE_ACCOUNT_CACHE_SIZE),
+                account_refreshed_at: HashMap::new(),
+                live_epochs: HashSet::new(),
+                next_hops: Default::default(),
+                reachability_scores: Default::default(),
+                route_back: RouteBackCache::default(),
+                store,
+                find_route_calls: 0,
+                last_routed: LruCache::new(LAST_ROUTED_CACHE_SIZE),
+            }),
+            snapshot: ArcSwap::default(),
+        }
+    }
+
+    pub(crate) fn set_reachability_scores(&self, scores: Arc<HashMap<PeerId, u32>>) {
+        self.inner.lock().reachability_scores = scores;
+    }
+
+    /// Sets the epochs of the chain head, for which the AnnounceAccounts don't expire.
+    pub(crate) fn set_live_epochs(&self, epochs: HashSet<EpochId>) {
+        self.inner.lock().live_epochs = epochs;
     }
 
     pub(crate) fn update(&self, next_hops: Arc<routing::NextHopTable>) {
-        self.0.lock().next_hops = next_hops;
+        let mut inner = self.inner.lock();
+        inner.next_hops = next_hops.clone();
+        self.snapshot.store(Arc::new(RoutingSnapshot {
+            reachable_peers: Arc::new(next_hops.keys().cloned().collect()),
+            account_owners: inner.account_owners.clone(),
+            next_hops,
+        }));
+    }
+
+    /// Stores a new snapshot with the current account owners.
+    /// Should be called with `inner` lock held, so that the snapshots are stored in order.
+    fn update_account_owners(&self, inner: &Inner) {
+        let snapshot = self.snapshot.load();
+        self.snapshot.store(Arc::new(RoutingSnapshot {
+            reachable_peers: snapshot.reachable_peers.clone(),
+            account_owners: inner.account_owners.clone(),
+            next_hops: snapshot.next_hops.clone(),
+        }));
+    }
+
+    /// Routing state as of the last update. Cheap, nothing is recomputed.
+    pub fn snapshot(&self) -> Arc<RoutingSnapshot> {
+        self.snapshot.load_full()
     }
 
     pub(crate) fn reachable_peers(&self) -> usize {
         // There is an implicit assumption here that all next_hops entries are non-empty.
         // To enforce this, we would need to make NextHopTable a newtype rather than an alias,
         // and add appropriate constructors, which would filter out empty entries.
-        self.0.lock().next_hops.len()
+        self.inner.lock().next_hops.len()
     }
 
     pub(crate) fn find_route(
@@ -116,7 +271,7 @@ impl RoutingTableView {
         clock: &time::Clock,
         target: &PeerIdOrHash,
     ) -> Result<PeerId, FindRouteError> {
-        let mut inner = self.0.lock();
+        let mut inner = self.inner.lock();
         match target {
             PeerIdOrHash::PeerId(peer_id) => inner.find_route_from_peer_id(peer_id),
             PeerIdOrHash::Hash(hash) => {
@@ -126,48 +281,157 @@ impl RoutingTableView {
     }
 
     pub(crate) fn view_route(&self, peer_id: &PeerId) -> Option<Vec<PeerId>> {
-        self.0.lock().next_hops.get(peer_id).cloned()
+        self.inner.lock().next_hops.get(peer_id).cloned()
     }
 
     /// Find peer that owns this AccountId.
-    pub(crate) fn account_owner(&self, account_id: &AccountId) -> Option<PeerId> {
-        self.0.lock().get_announce(account_id).map(|announce_account| announce_account.peer_id)
+    /// Expired AnnounceAccounts are ignored here and dropped later by `expire_accounts`,
+    /// so that this method doesn't write to store.
+    pub(crate) fn account_owner(
+        &self,
+        clock: &time::Clock,
+        account_id: &AccountId,
+    ) -> Option<PeerId> {
+        let now = clock.now_utc();
+        let mut inner = self.inner.lock();
+        let aa = match inner.account_peers.get(account_id) {
+            Some(aa) => aa.clone(),
+            None => {
+                let aa = inner.get_announce(account_id)?;
+                // The announcement has been loaded from store.
+                self.update_account_owners(&inner);
+                aa
+            }
+        };
+        if inner.is_expired(now, account_id) {
+            return None;
+        }
+        Some(aa.peer_id)
+    }
+
+    /// Accounts owned by peer_id, according to the AnnounceAccounts kept in memory.
+    pub(crate) fn accounts_for_peer(&self, peer_id: &PeerId) -> Vec<AccountId> {
+        let inner = self.inner.lock();
+        let mut accounts: Vec<_> =
+            inner.peer_accounts.get(peer_id).into_iter().flatten().cloned().collect();
+        accounts.sort();
+        accounts
     }
 
     /// Adds accounts to the routing table.
     /// Returns the diff: new values that should be broadcasted.
     /// Note: There is at most one peer id per account id.
-    pub(crate) fn add_accounts(&self, aas: Vec<AnnounceAccount>) -> Vec<AnnounceAccount> {
-        let mut inner = self.0.lock();
+    pub(crate) fn add_accounts(
+        &self,
+        clock: &time::Clock,
+        aas: Vec<AnnounceAccount>,
+    ) -> Vec<AnnounceAccount> {
+        let now = clock.now_utc();
+        let mut inner = self.inner.lock();
         let mut res = vec![];
         for aa in aas {
-            // We skip broadcasting stuff that is already broadcasted.
-            if inner.account_peers_broadcasted.get(&aa.account_id).map(|x| &x.epoch_id)
-                == Some(&aa.epoch_id)
-            {
-                continue;
+            // We skip broadcasting stuff that is already broadcasted, unless it is a refresh.
+            // A refresh is accepted only once in a while and only while the owner is reachable,
+            // so that the announcements of the nodes which went away eventually expire.
+            let old = inner
+                .account_peers_broadcasted
+                .get(&aa.account_id)
+                .filter(|old| old.epoch_id == aa.epoch_id)
+                .map(|old| old.peer_id == aa.peer_id);
+            if let Some(same_owner) = old {
+                if !same_owner
+                    || !inner.is_refresh_due(now, &aa.account_id)
+                    || !inner.next_hops.contains_key(&aa.peer_id)
+                {
+                    continue;
+                }
             }
-            inner.account_peers.put(aa.account_id.clone(), aa.clone());
+            inner.put_announce(now, aa.clone());
             inner.account_peers_broadcasted.put(aa.account_id.clone(), aa.clone());
             // Add account to store. Best effort
-            if let Err(e) = inner.store.set_account_announcement(&aa.account_id, &aa) {
+            if let Err(e) = inner.store.set_account_announcement(&aa.account_id, &aa, now) {
                 tracing::warn!(target: "network", "Error saving announce account to store: {:?}", e);
             }
             res.push(aa);
         }
+        if !res.is_empty() {
+            self.update_account_owners(&inner);
+        }
+        res
+    }
+
+    /// Removes the AnnounceAccounts which haven't been refreshed within ANNOUNCE_ACCOUNT_TTL,
+    /// both from memory and from store. Returns the number of removed accounts.
+    pub(crate) fn expire_accounts(&self, clock: &time::Clock) -> usize {
+        let now = clock.now_utc();
+        let mut inner = self.inner.lock();
+        let expired: Vec<_> = inner
+            .account_refreshed_at
+            .keys()
+            .filter(|id| inner.is_expired(now, id))
+            .cloned()
+            .collect();
+        for account_id in &expired {
+            inner.remove_expired(account_id);
+        }
+        if expired.is_empty() {
+            return 0;
+        }
+        self.update_account_owners(&inner);
+        // Store is updated without holding the lock.
+        let mut store = inner.store.clone();
+        drop(inner);
+        if let Err(e) = store.delete_account_announcements(&expired) {
+            tracing::warn!(target: "network", "Error removing announce accounts from store: {:?}", e);
+        }
+        expired.len()
+    }
+
+    /// Refreshes the AnnounceAccounts owned by `my_peer_id`, which haven't been refreshed
+    /// for ANNOUNCE_ACCOUNT_REFRESH_INTERVAL.
+    /// Returns the refreshed announcements, which should be rebroadcasted.
+    pub(crate) fn refresh_own_accounts(
+        &self,
+        clock: &time::Clock,
+        my_peer_id: &PeerId,
+    ) -> Vec<AnnounceAccount> {
+        let now = clock.now_utc();
+        let mut inner = self.inner.lock();
+        let res: Vec<_> = inner
+            .account_peers
+            .iter()
+            .filter(|(id, aa)| {
+                &aa.peer_id == my_peer_id
+                    && inner
+                        .account_age(now, id)
+                        .map_or(true, |age| age >= ANNOUNCE_ACCOUNT_REFRESH_INTERVAL)
+            })
+            .map(|(_, aa)| aa.clone())
+            .collect();
+        for aa in &res {
+            inner.account_refreshed_at.insert(aa.account_id.clone(), now);
+        }
+        // Store is updated without holding the lock.
+        let mut store = inner.store.clone();
+        drop(inner);
+        for aa in &res {
+            if let Err(e) = store.set_account_announcement(&aa.account_id, aa, now) {
+                tracing::warn!(target: "network", "Error saving announce account to store: {:?}", e);
+            }
+        }
         res
     }
 
     pub(crate) fn add_route_back(&self, clock: &time::Clock, hash: CryptoHash, peer_id: PeerId) {
-        self.0.lock().route_back.insert(clock, hash, peer_id);
+        self.inner.lock().route_back.insert(clock, hash, peer_id);
     }
 
     pub(crate) fn compare_route_back(&self, hash: CryptoHash, peer_id: &PeerId) -> bool {
-        self.0.lock().route_back.get(&hash).map_or(false, |value| value == peer_id)
+        self.inner.lock().route_back.get(&hash).map_or(false, |value| value == peer_id)
     }
 
     pub(crate) fn info(&self) -> RoutingTableInfo {
-        let inner = self.0.lock();
+        let inner = self.inner.lock();
         let account_peers =
             inner.account_peers.iter().map(|(id, aa)| (id.clone(), aa.peer_id.clone())).collect();
         RoutingTableInfo { account_peers, next_hops: inner.next_hops.clone() }
@@ -176,22 +440,29 @@ impl RoutingTableView {
     /// Public interface for `account_peers`.
     /// Get keys currently on cache.
     pub(crate) fn get_accounts_keys(&self) -> Vec<AccountId> {
-        self.0.lock().account_peers.iter().map(|(k, _v)| k).cloned().collect()
+        self.inner.lock().account_peers.iter().map(|(k, _v)| k).cloned().collect()
     }
 
     /// Get announce accounts on cache.
     pub(crate) fn get_announce_accounts(&self) -> Vec<AnnounceAccount> {
-        self.0.lock().account_peers.iter().map(|(_, v)| v.clone()).collect()
+        self.inner.lock().account_peers.iter().map(|(_, v)| v.clone()).collect()
     }
 
     /// Get AnnounceAccount for the given AccountIds, that we already broadcasted.
+    /// Announcements which are due for a refresh are skipped, so that their rebroadcast
+    /// is not rejected as a duplicate.
     pub(crate) fn get_broadcasted_announces<'a>(
         &'a self,
+        clock: &time::Clock,
         account_ids: impl Iterator<Item = &'a AccountId>,
     ) -> HashMap<AccountId, AnnounceAccount> {
-        let mut inner = self.0.lock();
+        let now = clock.now_utc();
+        let mut inner = self.inner.lock();
         account_ids
             .filter_map(|id| {
+                if inner.is_refresh_due(now, id) {
+                    return None;
+                }
                 inner.account_peers_broadcasted.get(id).map(|a| (id.clone(), a.clone()))
             })
             .collect()
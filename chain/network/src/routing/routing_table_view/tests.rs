@@ -6,8 +6,10 @@ use crate::test_utils::{random_epoch_id, random_peer_id};
 use crate::testonly::make_rng;
 use near_async::time;
 use near_crypto::Signature;
-use near_primitives::network::AnnounceAccount;
+use near_primitives::network::{AnnounceAccount, PeerId};
+use near_primitives::types::AccountId;
 use rand::seq::SliceRandom;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 #[test]
@@ -35,8 +37,41 @@ fn find_route() {
     }
 }
 
+#[test]
+fn find_route_prefers_higher_reachability_score() {
+    let mut rng = make_rng(385305732);
+    let clock = time::FakeClock::default();
+    let rng = &mut rng;
+    let store = crate::store::Store::from(near_store::db::TestDB::new());
+
+    let target = data::make_peer_id(rng);
+    let flaky = data::make_peer_id(rng);
+    let stable = data::make_peer_id(rng);
+    let rtv = RoutingTableView::new(store);
+    rtv.update(Arc::new(routing::NextHopTable::from([(
+        target.clone(),
+        vec![flaky.clone(), stable.clone()],
+    )])));
+    rtv.set_reachability_scores(Arc::new(HashMap::from([
+        (flaky.clone(), routing::MAX_REACHABILITY_SCORE / 2),
+        (stable.clone(), routing::MAX_REACHABILITY_SCORE),
+    ])));
+    for _ in 0..3 {
+        let got = rtv.find_route(&clock.clock(), &PeerIdOrHash::PeerId(target.clone())).unwrap();
+        assert_eq!(stable, got);
+    }
+
+    // Next hops with equal scores are used in a round robin fashion.
+    rtv.set_reachability_scores(Arc::default());
+    let got: HashSet<_> = (0..2)
+        .map(|_| rtv.find_route(&clock.clock(), &PeerIdOrHash::PeerId(target.clone())).unwrap())
+        .collect();
+    assert_eq!(HashSet::from([flaky, stable]), got);
+}
+
 #[test]
 fn announcement_same_epoch() {
+    let clock = time::FakeClock::default();
     let store = crate::store::Store::from(near_store::db::TestDB::new());
 
     let peer_id0 = random_peer_id();
@@ -63,20 +98,27 @@ fn announcement_same_epoch() {
     // Adding multiple announcements for the same account_id and epoch_id.
     // The first one should win.
     assert_eq!(
-        routing_table.add_accounts(vec![announce0.clone(), announce1.clone()]),
+        routing_table.add_accounts(&clock.clock(), vec![announce0.clone(), announce1.clone()]),
         vec![announce0.clone()]
     );
     assert_eq!(routing_table.get_announce_accounts(), vec![announce0.clone()]);
-    assert_eq!(routing_table.account_owner(&announce0.account_id).unwrap(), peer_id0);
+    assert_eq!(
+        routing_table.account_owner(&clock.clock(), &announce0.account_id).unwrap(),
+        peer_id0
+    );
 
     // Adding a conflicting announcement later. Should be a noop.
-    assert_eq!(routing_table.add_accounts(vec![announce1]), vec![]);
+    assert_eq!(routing_table.add_accounts(&clock.clock(), vec![announce1]), vec![]);
     assert_eq!(routing_table.get_announce_accounts(), vec![announce0.clone()]);
-    assert_eq!(routing_table.account_owner(&announce0.account_id).unwrap(), peer_id0);
+    assert_eq!(
+        routing_table.account_owner(&clock.clock(), &announce0.account_id).unwrap(),
+        peer_id0
+    );
 }
 
 #[test]
 fn dont_load_on_build() {
+    let clock = time::FakeClock::default();
     let store = crate::store::Store::from(near_store::db::TestDB::new());
 
     let peer_id0 = random_peer_id();
@@ -101,8 +143,8 @@ fn dont_load_on_build() {
         signature: Signature::default(),
     };
 
-    routing_table.add_accounts(vec![announce0.clone()]);
-    routing_table.add_accounts(vec![announce1.clone()]);
+    routing_table.add_accounts(&clock.clock(), vec![announce0.clone()]);
+    routing_table.add_accounts(&clock.clock(), vec![announce1.clone()]);
     let accounts: Vec<AnnounceAccount> = routing_table.get_announce_accounts();
     assert!(vec![announce0, announce1].iter().all(|announce| { accounts.contains(&announce) }));
     assert_eq!(accounts.len(), 2);
@@ -113,6 +155,7 @@ fn dont_load_on_build() {
 
 #[test]
 fn load_from_disk() {
+    let clock = time::FakeClock::default();
     let store = crate::store::Store::from(near_store::db::TestDB::new());
 
     let peer_id0 = random_peer_id();
@@ -129,12 +172,230 @@ fn load_from_disk() {
     };
 
     // Announcement is added to cache of the first routing table and to disk
-    routing_table.add_accounts(vec![announce0.clone()]);
+    routing_table.add_accounts(&clock.clock(), vec![announce0.clone()]);
     assert_eq!(routing_table.get_announce_accounts().len(), 1);
     // Cache of second routing table is empty
     assert_eq!(routing_table1.get_announce_accounts().len(), 0);
     // Try to find this peer and load it from disk
-    assert_eq!(routing_table1.account_owner(&announce0.account_id).unwrap(), peer_id0);
+    assert_eq!(
+        routing_table1.account_owner(&clock.clock(), &announce0.account_id).unwrap(),
+        peer_id0
+    );
     // Cache of second routing table should contain account loaded from disk
     assert_eq!(routing_table1.get_announce_accounts().len(), 1);
 }
+
+#[test]
+fn snapshot() {
+    let clock = time::FakeClock::default();
+    let store = crate::store::Store::from(near_store::db::TestDB::new());
+    let routing_table = RoutingTableView::new(store);
+
+    let peer_id0 = random_peer_id();
+    let peer_id1 = random_peer_id();
+    let announce0 = AnnounceAccount {
+        account_id: "near0".parse().unwrap(),
+        peer_id: peer_id0.clone(),
+        epoch_id: random_epoch_id(),
+        signature: Signature::default(),
+    };
+    let next_hops: routing::NextHopTable =
+        [(peer_id0.clone(), vec![peer_id1.clone()]), (peer_id1.clone(), vec![peer_id1.clone()])]
+            .into_iter()
+            .collect();
+    let next_hops = Arc::new(next_hops);
+
+    let before = routing_table.snapshot();
+    routing_table.update(next_hops.clone());
+    routing_table.add_accounts(&clock.clock(), vec![announce0.clone()]);
+    let after = routing_table.snapshot();
+
+    // The snapshot reflects the state at the time it was taken.
+    assert!(before.account_owners.is_empty());
+    assert!(before.reachable_peers.is_empty());
+    assert_eq!(Some(&peer_id0), after.account_owners.get(&announce0.account_id));
+    assert_eq!(HashSet::from([peer_id0, peer_id1]), *after.reachable_peers);
+    assert_eq!(next_hops, after.next_hops);
+}
+
+#[test]
+fn account_expiry() {
+    let clock = time::FakeClock::default();
+    let store = crate::store::Store::from(near_store::db::TestDB::new());
+    let routing_table = RoutingTableView::new(store.clone());
+
+    let announce0 = AnnounceAccount {
+        account_id: "near0".parse().unwrap(),
+        peer_id: random_peer_id(),
+        epoch_id: random_epoch_id(),
+        signature: Signature::default(),
+    };
+    let announce1 = AnnounceAccount {
+        account_id: "near1".parse().unwrap(),
+        peer_id: random_peer_id(),
+        epoch_id: random_epoch_id(),
+        signature: Signature::default(),
+    };
+    // Only the owner of announce1 is reachable, so only announce1 can be refreshed.
+    let next_hops: routing::NextHopTable =
+        [(announce1.peer_id.clone(), vec![announce1.peer_id.clone()])].into_iter().collect();
+    routing_table.update(Arc::new(next_hops));
+    routing_table.add_accounts(&clock.clock(), vec![announce0.clone(), announce1.clone()]);
+
+    // The rebroadcast refreshes announce1, but not announce0, whose owner is unreachable.
+    clock.advance(ANNOUNCE_ACCOUNT_TTL / 2);
+    assert_eq!(
+        routing_table.add_accounts(&clock.clock(), vec![announce0.clone(), announce1.clone()]),
+        vec![announce1.clone()]
+    );
+
+    // announce0 hasn't been refreshed within the TTL, so it is gone, also from the store.
+    clock.advance(ANNOUNCE_ACCOUNT_TTL / 2);
+    assert_eq!(None, routing_table.account_owner(&clock.clock(), &announce0.account_id));
+    assert_eq!(
+        Some(announce1.peer_id.clone()),
+        routing_table.account_owner(&clock.clock(), &announce1.account_id)
+    );
+    assert_eq!(1, routing_table.expire_accounts(&clock.clock()));
+    let routing_table1 = RoutingTableView::new(store);
+    assert_eq!(None, routing_table1.account_owner(&clock.clock(), &announce0.account_id));
+
+    // Once announce0 has expired, it can be announced again.
+    assert_eq!(
+        routing_table.add_accounts(&clock.clock(), vec![announce0.clone()]),
+        vec![announce0.clone()]
+    );
+
+    // announce1 expires as well, if it is not refreshed.
+    clock.advance(ANNOUNCE_ACCOUNT_TTL / 2);
+    assert_eq!(1, routing_table.expire_accounts(&clock.clock()));
+    assert_eq!(None, routing_table.account_owner(&clock.clock(), &announce1.account_id));
+    assert_eq!(vec![announce0], routing_table.get_announce_accounts());
+}
+
+#[test]
+fn expired_account_is_not_loaded_from_store() {
+    let clock = time::FakeClock::default();
+    let store = crate::store::Store::from(near_store::db::TestDB::new());
+    let routing_table = RoutingTableView::new(store.clone());
+
+    let announce0 = AnnounceAccount {
+        account_id: "near0".parse().unwrap(),
+        peer_id: random_peer_id(),
+        epoch_id: random_epoch_id(),
+        signature: Signature::default(),
+    };
+    routing_table.add_accounts(&clock.clock(), vec![announce0.clone()]);
+
+    // The refresh time is persisted, so the announcement loaded from store expires on time.
+    clock.advance(ANNOUNCE_ACCOUNT_TTL / 2);
+    let routing_table1 = RoutingTableView::new(store.clone());
+    assert_eq!(
+        Some(announce0.peer_id.clone()),
+        routing_table1.account_owner(&clock.clock(), &announce0.account_id)
+    );
+    clock.advance(ANNOUNCE_ACCOUNT_TTL / 2);
+    let routing_table2 = RoutingTableView::new(store);
+    assert_eq!(None, routing_table2.account_owner(&clock.clock(), &announce0.account_id));
+    assert_eq!(1, routing_table2.expire_accounts(&clock.clock()));
+}
+
+#[test]
+fn live_epoch_accounts_dont_expire() {
+    let clock = time::FakeClock::default();
+    let store = crate::store::Store::from(near_store::db::TestDB::new());
+    let routing_table = RoutingTableView::new(store);
+
+    let announce0 = AnnounceAccount {
+        account_id: "near0".parse().unwrap(),
+        peer_id: random_peer_id(),
+        epoch_id: random_epoch_id(),
+        signature: Signature::default(),
+    };
+    routing_table.set_live_epochs(HashSet::from([announce0.epoch_id.clone()]));
+    routing_table.add_accounts(&clock.clock(), vec![announce0.clone()]);
+
+    // Nodes running older binaries don't refresh their announcements within an epoch.
+    clock.advance(ANNOUNCE_ACCOUNT_TTL * 2);
+    assert_eq!(0, routing_table.expire_accounts(&clock.clock()));
+    assert_eq!(
+        Some(announce0.peer_id.clone()),
+        routing_table.account_owner(&clock.clock(), &announce0.account_id)
+    );
+
+    // Once the epoch is over, the announcement expires.
+    routing_table.set_live_epochs(HashSet::from([random_epoch_id(), random_epoch_id()]));
+    assert_eq!(None, routing_table.account_owner(&clock.clock(), &announce0.account_id));
+    assert_eq!(1, routing_table.expire_accounts(&clock.clock()));
+    assert_eq!(Vec::<AnnounceAccount>::new(), routing_table.get_announce_accounts());
+}
+
+#[test]
+fn refresh_own_accounts() {
+    let clock = time::FakeClock::default();
+    let store = crate::store::Store::from(near_store::db::TestDB::new());
+    let routing_table = RoutingTableView::new(store);
+
+    let my_peer_id = random_peer_id();
+    let announce0 = AnnounceAccount {
+        account_id: "near0".parse().unwrap(),
+        peer_id: my_peer_id.clone(),
+        epoch_id: random_epoch_id(),
+        signature: Signature::default(),
+    };
+    let announce1 = AnnounceAccount {
+        account_id: "near1".parse().unwrap(),
+        peer_id: random_peer_id(),
+        epoch_id: random_epoch_id(),
+        signature: Signature::default(),
+    };
+    routing_table.add_accounts(&clock.clock(), vec![announce0.clone(), announce1]);
+    assert_eq!(routing_table.refresh_own_accounts(&clock.clock(), &my_peer_id), vec![]);
+
+    // Our own account is refreshed periodically, so it never expires.
+    for _ in 0..5 {
+        clock.advance(ANNOUNCE_ACCOUNT_REFRESH_INTERVAL);
+        assert_eq!(
+            routing_table.refresh_own_accounts(&clock.clock(), &my_peer_id),
+            vec![announce0.clone()]
+        );
+    }
+    routing_table.expire_accounts(&clock.clock());
+    assert_eq!(vec![announce0], routing_table.get_announce_accounts());
+}
+
+#[test]
+fn accounts_for_peer() {
+    let clock = time::FakeClock::default();
+    let store = crate::store::Store::from(near_store::db::TestDB::new());
+    let routing_table = RoutingTableView::new(store);
+
+    let peer_id0 = random_peer_id();
+    let peer_id1 = random_peer_id();
+    let announce = |account_id: &str, peer_id: &PeerId| AnnounceAccount {
+        account_id: account_id.parse().unwrap(),
+        peer_id: peer_id.clone(),
+        epoch_id: random_epoch_id(),
+        signature: Signature::default(),
+    };
+    let announce0 = announce("near0", &peer_id0);
+    let announce1 = announce("near1", &peer_id0);
+    routing_table.add_accounts(&clock.clock(), vec![announce0.clone(), announce1.clone()]);
+    assert_eq!(
+        vec![announce0.account_id.clone(), announce1.account_id.clone()],
+        routing_table.accounts_for_peer(&peer_id0)
+    );
+    assert_eq!(Vec::<AccountId>::new(), routing_table.accounts_for_peer(&peer_id1));
+
+    // An announcement for a newer epoch moves the account to the other peer.
+    clock.advance(ANNOUNCE_ACCOUNT_TTL / 2);
+    routing_table.add_accounts(&clock.clock(), vec![announce("near1", &peer_id1)]);
+    assert_eq!(vec![announce0.account_id.clone()], routing_table.accounts_for_peer(&peer_id0));
+    assert_eq!(vec![announce1.account_id.clone()], routing_table.accounts_for_peer(&peer_id1));
+
+    // Expired accounts are dropped from the index.
+    clock.advance(ANNOUNCE_ACCOUNT_TTL / 2);
+    assert_eq!(1, routing_table.expire_accounts(&clock.clock()));
+    assert_eq!(Vec::<AccountId>::new(), routing_table.accounts_for_peer(&peer_id0));
+    assert_eq!(vec![announce1.account_id], routing_table.accounts_for_peer(&peer_id1));
+}
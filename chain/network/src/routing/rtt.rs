@@ -0,0 +1,120 @@
+use crate::stats::metrics;
+use near_async::time;
+use near_primitives::network::PeerId;
+use std::collections::HashMap;
+
+/// Tracks the round trip time of routed messages, measured via Ping/Pong.
+///
+/// Every sent Ping is remembered together with the time it was sent.
+/// When the matching Pong arrives, the round trip time to the Ping target is recorded.
+/// Pings which are not answered within `timeout` are considered lost.
+pub(crate) struct RttTracker {
+    /// How long to wait for a Pong, before considering the Ping lost.
+    timeout: time::Duration,
+    /// Pings which haven't been answered yet, keyed by (nonce, target).
+    outstanding: HashMap<(u64, PeerId), time::Instant>,
+    /// Last measured round trip time to each peer.
+    rtt: HashMap<PeerId, time::Duration>,
+    /// Time of the last matching Pong received from each peer.
+    last_pong: HashMap<PeerId, time::Instant>,
+}
+
+impl RttTracker {
+    pub fn new(timeout: time::Duration) -> Self {
+        Self {
+            timeout,
+            outstanding: HashMap::new(),
+            rtt: HashMap::new(),
+            last_pong: HashMap::new(),
+        }
+    }
+
+    /// Last measured round trip time to `peer_id`.
+    #[cfg(test)]
+    pub fn get(&self, peer_id: &PeerId) -> Option<time::Duration> {
+        self.rtt.get(peer_id).copied()
+    }
+
+    /// Time of the last matching Pong received from `peer_id`.
+    pub fn last_pong(&self, peer_id: &PeerId) -> Option<time::Instant> {
+        self.last_pong.get(peer_id).copied()
+    }
+
+    /// Registers a Ping sent at `now`.
+    pub fn ping_sent(&mut self, now: time::Instant, nonce: u64, target: PeerId) {
+        self.expire(now);
+        self.outstanding.insert((nonce, target), now);
+    }
+
+    /// Registers a Pong received at `now` and returns the measured round trip time.
+    /// Returns None if there is no matching outstanding Ping.
+    pub fn pong_received(
+        &mut self,
+        now: time::Instant,
+        nonce: u64,
+        source: &PeerId,
+    ) -> Option<time::Duration> {
+        let sent_at = self.outstanding.remove(&(nonce, source.clone()))?;
+        let rtt = now - sent_at;
+        metrics::PEER_RTT_SECONDS.observe(rtt.as_seconds_f64());
+        self.rtt.insert(source.clone(), rtt);
+        self.last_pong.insert(source.clone(), now);
+        Some(rtt)
+    }
+
+    /// Forgets the measurements of `peer_id`.
+    pub fn remove(&mut self, peer_id: &PeerId) {
+        self.rtt.remove(peer_id);
+        self.last_pong.remove(peer_id);
+    }
+
+    /// Drops the Pings which haven't been answered within the timeout.
+    fn expire(&mut self, now: time::Instant) {
+        let timeout = self.timeout;
+        self.outstanding.retain(|_, sent_at| {
+            if now - *sent_at < timeout {
+                return true;
+            }
+            metrics::PING_LOST.inc();
+            false
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::network_protocol::testonly as data;
+    use crate::testonly::make_rng;
+
+    #[test]
+    fn rtt() {
+        let clock = time::FakeClock::default();
+        let mut rng = make_rng(921853233);
+        let peer0 = data::make_peer_id(&mut rng);
+        let peer1 = data::make_peer_id(&mut rng);
+        let mut tracker = RttTracker::new(time::Duration::seconds(10));
+
+        tracker.ping_sent(clock.now(), 0, peer0.clone());
+        tracker.ping_sent(clock.now(), 1, peer1.clone());
+        clock.advance(time::Duration::milliseconds(300));
+        // Pong from a wrong peer is ignored.
+        assert_eq!(None, tracker.pong_received(clock.now(), 0, &peer1));
+        let want = time::Duration::milliseconds(300);
+        assert_eq!(Some(want), tracker.pong_received(clock.now(), 0, &peer0));
+        assert_eq!(Some(want), tracker.get(&peer0));
+        assert_eq!(Some(clock.now()), tracker.last_pong(&peer0));
+        assert_eq!(None, tracker.last_pong(&peer1));
+
+        // Unanswered ping expires.
+        clock.advance(time::Duration::seconds(10));
+        tracker.ping_sent(clock.now(), 2, peer0.clone());
+        assert_eq!(None, tracker.pong_received(clock.now(), 1, &peer1));
+        assert_eq!(None, tracker.get(&peer1));
+
+        // Measurements of a removed peer are forgotten.
+        tracker.remove(&peer0);
+        assert_eq!(None, tracker.get(&peer0));
+        assert_eq!(None, tracker.last_pong(&peer0));
+    }
+}
@@ -106,6 +106,7 @@ impl<M: prometheus::core::Metric> std::ops::Deref for MetricGuard<M> {
 }
 
 pub(crate) type IntGaugeGuard = MetricGuard<prometheus::IntGauge>;
+pub(crate) type IntCounterGuard = MetricGuard<prometheus::IntCounter>;
 
 pub static PEER_CONNECTIONS: Lazy<Gauge<Connection>> =
     Lazy::new(|| Gauge::new("near_peer_connections", "Number of connected peers").unwrap());
@@ -140,6 +141,29 @@ pub(crate) static PEER_MSG_READ_LATENCY: Lazy<Histogram> = Lazy::new(|| {
     .unwrap()
 });
 
+pub(crate) static PEER_RATE_LIMITED: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_peer_rate_limited",
+        "Number of routed messages dropped, because the sending peer exceeded its rate limit. \
+        Reported only for the connected peers.",
+        &["peer_id"],
+    )
+    .unwrap()
+});
+
+pub(crate) static PEER_RTT_SECONDS: Lazy<Histogram> = Lazy::new(|| {
+    try_create_histogram_with_buckets(
+        "near_peer_rtt_seconds",
+        "Round trip time of routed messages, measured via Ping/Pong",
+        exponential_buckets(0.001, 1.5, 25).unwrap(),
+    )
+    .unwrap()
+});
+pub(crate) static PING_LOST: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter("near_ping_lost", "Number of Pings which haven't been answered in time")
+        .unwrap()
+});
+
 pub(crate) static PEER_DATA_SENT_BYTES: Lazy<IntCounter> = Lazy::new(|| {
     try_create_int_counter("near_peer_data_sent_bytes", "Total data sent to peers").unwrap()
 });
@@ -233,6 +257,17 @@ pub(crate) static ROUTING_TABLE_RECALCULATION_HISTOGRAM: Lazy<Histogram> = Lazy:
     )
     .unwrap()
 });
+pub(crate) static EDGE_VERIFICATION_SECONDS: Lazy<Histogram> = Lazy::new(|| {
+    try_create_histogram("near_edge_verification_seconds", "Time spent verifying a set of edges")
+        .unwrap()
+});
+pub(crate) static EDGES_INVALID: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_edges_invalid",
+        "Number of edges rejected due to invalid signature",
+    )
+    .unwrap()
+});
 pub(crate) static EDGE_UPDATES: Lazy<IntCounter> =
     Lazy::new(|| try_create_int_counter("near_edge_updates", "Unique edge updates").unwrap());
 pub(crate) static EDGE_NONCE: Lazy<IntCounterVec> = Lazy::new(|| {
@@ -246,6 +281,47 @@ pub(crate) static EDGE_TOTAL: Lazy<IntGauge> = Lazy::new(|| {
         .unwrap()
 });
 
+pub(crate) static EDGE_FLAP_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_edge_flap_total",
+        "Number of edge updates which toggled the edge state between active and removed",
+    )
+    .unwrap()
+});
+
+pub(crate) static EDGES_REJECTED: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_edges_rejected",
+        "Number of edges rejected because the in-memory edge limit has been reached",
+    )
+    .unwrap()
+});
+
+pub(crate) static PEER_REACHABLE_ADDED: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_peer_reachable_added",
+        "Number of peers which became reachable, while not being tracked in memory",
+    )
+    .unwrap()
+});
+
+pub(crate) static PEER_PRUNED: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_peer_pruned",
+        "Number of unreachable peers pruned from the in-memory routing graph",
+    )
+    .unwrap()
+});
+
+pub(crate) static GRAPH_COMPONENTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_graph_components",
+        "Number of graph components moved between memory and DB, or deleted from DB",
+        &["op"],
+    )
+    .unwrap()
+});
+
 pub(crate) static EDGE_TOMBSTONE_SENDING_SKIPPED: Lazy<IntCounter> = Lazy::new(|| {
     try_create_int_counter(
         "near_edge_tombstone_sending_skip",
@@ -303,6 +379,22 @@ pub(crate) static PEER_REACHABLE: Lazy<IntGauge> = Lazy::new(|| {
     )
     .unwrap()
 });
+pub(crate) static ROUTING_PATH_LENGTH: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_routing_path_length",
+        "Number of reachable peers by the number of hops to them, as of the last routing table recalculation",
+        &["hops"],
+    )
+    .unwrap()
+});
+
+pub(crate) static PEER_BEYOND_MAX_ROUTE_HOPS: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_peer_beyond_max_route_hops",
+        "Peers left unreachable, because they are just one hop beyond the route hop limit",
+    )
+    .unwrap()
+});
 static DROPPED_MESSAGE_COUNT: Lazy<IntCounterVec> = Lazy::new(|| {
     try_create_int_counter_vec(
         "near_dropped_message_by_type_and_reason_count",
@@ -350,6 +442,81 @@ pub(crate) static CONNECTED_TO_MYSELF: Lazy<IntCounter> = Lazy::new(|| {
     .unwrap()
 });
 
+pub(crate) static ROUTE_BACK_CACHE_SIZE: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_route_back_cache_size",
+        "Total number of entries in the route back caches",
+    )
+    .unwrap()
+});
+pub(crate) static ROUTE_BACK_CACHE_EVICTED: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_route_back_cache_evicted",
+        "Number of route back cache entries evicted before being used, because the cache was full or the entry expired",
+    )
+    .unwrap()
+});
+pub(crate) static CONNECTION_POOL_READY: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_connection_pool_ready",
+        "Number of connections which have completed the handshake, per connection pool",
+        &["pool"],
+    )
+    .unwrap()
+});
+pub(crate) static CONNECTION_POOL_OUTBOUND_HANDSHAKES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_connection_pool_outbound_handshakes",
+        "Number of outbound handshakes in progress, per connection pool",
+        &["pool"],
+    )
+    .unwrap()
+});
+pub(crate) static INBOUND_HANDSHAKE_PERMITS_AVAILABLE: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_inbound_handshake_permits_available",
+        "Number of inbound handshakes which can be started before new inbound connections get rejected",
+    )
+    .unwrap()
+});
+pub(crate) static TIER1_CONNECT_FAILURES: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_tier1_connect_failures",
+        "Number of failed attempts to connect to proxies of other TIER1 nodes",
+    )
+    .unwrap()
+});
+pub(crate) static TIER1_CONNECTIONS_CLOSED: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_tier1_connections_closed",
+        "Number of TIER1 connections closed by tier1_connect(), because they were redundant or no longer TIER1",
+    )
+    .unwrap()
+});
+pub(crate) static TIER1_PROXIES_ADVERTISEMENTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_tier1_proxies_advertisements",
+        "Number of advertisements of this node's TIER1 proxies, by whether they were broadcasted or skipped because nothing has changed",
+        &["result"],
+    )
+    .unwrap()
+});
+pub(crate) static ACCOUNTS_DATA_EXPIRED: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_accounts_data_expired",
+        "Number of AccountData entries dropped from the cache, because they were too old",
+    )
+    .unwrap()
+});
+
+pub(crate) static TIER1_LIVENESS_DROPS: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_tier1_liveness_drops",
+        "Number of TIER1 connections closed, because they didn't answer consecutive liveness pings",
+    )
+    .unwrap()
+});
+
 pub(crate) static ALREADY_CONNECTED_ACCOUNT: Lazy<IntCounter> = Lazy::new(|| {
     try_create_int_counter(
         "near_already_connected_account",
@@ -358,6 +525,29 @@ pub(crate) static ALREADY_CONNECTED_ACCOUNT: Lazy<IntCounter> = Lazy::new(|| {
     .unwrap()
 });
 
+pub(crate) static TXNS_SINCE_LAST_BLOCK: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_txns_since_last_block",
+        "Number of ForwardTx messages accepted since the last block message",
+    )
+    .unwrap()
+});
+
+pub(crate) static ANNOUNCE_ACCOUNTS: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_announce_accounts",
+        "Number of account to peer mappings kept in the routing table",
+    )
+    .unwrap()
+});
+pub(crate) static ANNOUNCE_ACCOUNTS_EXPIRED: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_announce_accounts_expired",
+        "Number of account to peer mappings dropped because they haven't been refreshed in time",
+    )
+    .unwrap()
+});
+
 pub(crate) static ACCOUNT_TO_PEER_LOOKUPS: Lazy<IntCounterVec> = Lazy::new(|| {
     try_create_int_counter_vec(
         "near_account_to_peer_lookups",
@@ -426,6 +616,21 @@ fn record_routed_msg_hops(msg: &RoutedMessageV2) {
     }
 }
 
+/// Publishes the number of reachable peers per hop count, where `path_lengths[i]` is the number
+/// of peers exactly i hops away. Every label is set on every call, so that the hop counts which
+/// are no longer present are reset to 0.
+pub(crate) fn set_routing_path_lengths(path_lengths: &[u64]) {
+    const MAX_NUM_HOPS: usize = 10;
+    // Peers further away are counted under MAX_NUM_HOPS, to bound the number of labels.
+    let mut counts = [0; MAX_NUM_HOPS];
+    for (num_hops, n) in path_lengths.iter().enumerate().skip(1) {
+        counts[num_hops.min(MAX_NUM_HOPS) - 1] += n;
+    }
+    for (i, n) in counts.iter().enumerate() {
+        ROUTING_PATH_LENGTH.with_label_values(&[&(i + 1).to_string()]).set(*n as i64);
+    }
+}
+
 #[derive(Clone, Copy, strum::AsRefStr)]
 pub(crate) enum MessageDropped {
     NoRouteFound,
@@ -434,6 +639,8 @@ pub(crate) enum MessageDropped {
     MaxCapacityExceeded,
     TransactionsPerBlockExceeded,
     Duplicate,
+    RetryWindowExpired,
+    RetryBufferFull,
 }
 
 impl MessageDropped {
@@ -3,6 +3,7 @@
 /// in particular schema::StoreUpdate is not exported.
 use crate::network_protocol::Edge;
 use crate::types::ConnectionInfo;
+use near_async::time;
 use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::types::AccountId;
 use std::collections::HashSet;
@@ -42,22 +43,34 @@ pub(crate) struct Store(schema::Store);
 /// DBCol::PeerComponent      -> Mapping from `peer_id` to last component nonce if there
 ///                          exists one it belongs to.
 impl Store {
-    /// Inserts (account_id,aa) to the AccountAnnouncements column.
+    /// Inserts (account_id,(aa,refreshed_at)) to the AccountAnnouncements column.
     pub fn set_account_announcement(
         &mut self,
         account_id: &AccountId,
         aa: &AnnounceAccount,
+        refreshed_at: time::Utc,
     ) -> Result<(), Error> {
         let mut update = self.0.new_update();
-        update.set::<schema::AccountAnnouncements>(account_id, aa);
+        update.set::<schema::AccountAnnouncements>(account_id, &(aa.clone(), Some(refreshed_at)));
         self.0.commit(update).map_err(Error)
     }
 
-    /// Fetches row with key account_id from the AccountAnnouncements column.
+    /// Deletes rows with keys account_ids from the AccountAnnouncements column.
+    pub fn delete_account_announcements(&mut self, account_ids: &[AccountId]) -> Result<(), Error> {
+        let mut update = self.0.new_update();
+        for account_id in account_ids {
+            update.delete::<schema::AccountAnnouncements>(account_id);
+        }
+        self.0.commit(update).map_err(Error)
+    }
+
+    /// Fetches row with key account_id from the AccountAnnouncements column,
+    /// together with the time the announcement has been last refreshed.
+    /// The refresh time is None for the rows written by older binaries.
     pub fn get_account_announcement(
         &self,
         account_id: &AccountId,
-    ) -> Result<Option<AnnounceAccount>, Error> {
+    ) -> Result<Option<(AnnounceAccount, Option<time::Utc>)>, Error> {
         self.0.get::<schema::AccountAnnouncements>(account_id).map_err(Error)
     }
 
@@ -116,6 +129,39 @@ impl Store {
         self.0.commit(update).map_err(Error)?;
         Ok(edges)
     }
+
+    /// Deletes from DB the components which no peer is a member of any more.
+    /// A component becomes orphaned once all its members have been moved to newer
+    /// components (see the example in the Graph's load_component()).
+    /// Returns the number of deleted components. O(size of the component columns).
+    ///
+    /// It is safe to call it concurrently with push_component() and pop_component():
+    /// the components are listed before their members, so a component pushed in
+    /// the meantime is not listed, and an orphaned component never becomes live again,
+    /// because the component nonces are not reused.
+    pub fn compact_components(&mut self) -> Result<usize, Error> {
+        let mut components = vec![];
+        for row in self.0.iter::<schema::ComponentEdges>() {
+            let (component, _) = row.map_err(Error)?;
+            components.push(component);
+        }
+        let mut live = HashSet::new();
+        for row in self.0.iter::<schema::PeerComponent>() {
+            let (_, component) = row.map_err(Error)?;
+            live.insert(component);
+        }
+        let orphans: Vec<_> = components.into_iter().filter(|c| !live.contains(c)).collect();
+        if orphans.is_empty() {
+            return Ok(0);
+        }
+        debug!(target: "network", "compact_components: deleting {} orphaned components", orphans.len());
+        let mut update = self.0.new_update();
+        for component in &orphans {
+            update.delete::<schema::ComponentEdges>(component);
+        }
+        self.0.commit(update).map_err(Error)?;
+        Ok(orphans.len())
+    }
 }
 
 // ConnectionStore storage.
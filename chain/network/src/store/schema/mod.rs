@@ -11,8 +11,6 @@ use near_store::DBCol;
 use std::io;
 use std::sync::Arc;
 
-#[cfg(test)]
-mod testonly;
 #[cfg(test)]
 mod tests;
 
@@ -27,6 +25,31 @@ impl Format for AccountIdFormat {
     }
 }
 
+/// AnnounceAccount together with the time it has been last refreshed.
+/// The refresh time is appended to the borsh encoding of the AnnounceAccount
+/// as UNIX timestamp in nanos. It is absent in the rows written by older binaries,
+/// in which case it is decoded as None.
+pub struct AnnounceAccountFormat;
+impl Format for AnnounceAccountFormat {
+    type T = (AnnounceAccount, Option<time::Utc>);
+    fn encode<W: io::Write>(a: &Self::T, w: &mut W) -> io::Result<()> {
+        a.0.serialize(w)?;
+        if let Some(t) = a.1 {
+            (t.unix_timestamp_nanos() as u64).serialize(w)?;
+        }
+        Ok(())
+    }
+    fn decode(mut a: &[u8]) -> io::Result<Self::T> {
+        let aa = AnnounceAccount::deserialize(&mut a)?;
+        if a.is_empty() {
+            return Ok((aa, None));
+        }
+        let t = time::Utc::from_unix_timestamp_nanos(u64::try_from_slice(a)? as i128)
+            .map_err(invalid_data)?;
+        Ok((aa, Some(t)))
+    }
+}
+
 /// A Borsh representation of the primitives::ConnectionInfo.
 #[derive(BorshSerialize, BorshDeserialize)]
 pub(super) struct ConnectionInfoRepr {
@@ -93,7 +116,7 @@ pub(super) struct AccountAnnouncements;
 impl Column for AccountAnnouncements {
     const COL: DBCol = DBCol::AccountAnnouncements;
     type Key = AccountIdFormat;
-    type Value = Borsh<AnnounceAccount>;
+    type Value = AnnounceAccountFormat;
 }
 
 pub(super) struct RecentOutboundConnections;
@@ -243,6 +266,16 @@ impl Store {
             None => None,
         })
     }
+
+    pub fn iter<C: Column>(
+        &self,
+    ) -> impl Iterator<Item = Result<(<C::Key as Format>::T, <C::Value as Format>::T), Error>> + '_
+    {
+        debug_assert!(!C::COL.is_rc());
+        self.0
+            .iter_raw_bytes(C::COL)
+            .map(|item| item.and_then(|(k, v)| Ok((C::Key::decode(&k)?, C::Value::decode(&v)?))))
+    }
 }
 
 impl From<Arc<dyn near_store::db::Database>> for Store {
@@ -11,3 +11,18 @@ fn borsh_wrapper_is_transparent() {
     let e = data::make_edge(&s1, &s2, 1);
     assert_eq!(Borsh(e.clone()).try_to_vec().unwrap(), e.try_to_vec().unwrap());
 }
+
+#[test]
+fn announce_account_format() {
+    let mut rng = make_rng(423423);
+    let rng = &mut rng;
+    let aa = data::make_announce_account(rng);
+    let t = time::Utc::from_unix_timestamp(1_700_000_000).unwrap();
+
+    let encoded = to_vec::<AnnounceAccountFormat>(&(aa.clone(), Some(t)));
+    assert_eq!((aa.clone(), Some(t)), AnnounceAccountFormat::decode(&encoded).unwrap());
+
+    // Rows written by older binaries contain just the AnnounceAccount.
+    let legacy = to_vec::<Borsh<AnnounceAccount>>(&aa);
+    assert_eq!((aa, None), AnnounceAccountFormat::decode(&legacy).unwrap());
+}
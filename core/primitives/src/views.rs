@@ -455,9 +455,60 @@ pub struct EdgeView {
     pub nonce: u64,
 }
 
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+pub struct EdgeTransitionView {
+    pub nonce: u64,
+    pub state: String,
+    /// Unix timestamp at which the update has been added to the graph.
+    pub timestamp: i64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+pub struct EdgeHistoryView {
+    pub peer0: PeerId,
+    pub peer1: PeerId,
+    /// Recent transitions of the edge, oldest first.
+    pub transitions: Vec<EdgeTransitionView>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+pub struct LatencyStatsView {
+    /// Number of samples taken into account.
+    pub samples: u64,
+    pub p50_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+pub struct PeerAccountsView {
+    pub peer_id: PeerId,
+    /// Accounts owned by the peer, according to the known AnnounceAccounts.
+    pub accounts: Vec<AccountId>,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
 pub struct NetworkGraphView {
     pub edges: Vec<EdgeView>,
+    /// Number of edges in the graph, including the removed ones.
+    #[serde(default)]
+    pub total_edges: u64,
+    /// Number of active edges in the graph.
+    #[serde(default)]
+    pub active_edges: u64,
+    /// Number of peers reachable from this node.
+    #[serde(default)]
+    pub reachable_peer_count: u64,
+    /// Recent transitions of the edges adjacent to this node.
+    #[serde(default)]
+    pub local_edge_history: Vec<EdgeHistoryView>,
+    /// Latency percentiles of the recent routing table recomputations.
+    /// None if the routing table hasn't been computed yet.
+    #[serde(default)]
+    pub recalc_latency: Option<LatencyStatsView>,
+    /// Accounts announced by each peer, ordered by peer.
+    #[serde(default)]
+    pub peer_accounts: Vec<PeerAccountsView>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]